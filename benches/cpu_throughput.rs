@@ -0,0 +1,105 @@
+//! Measures how fast the interpreter runs a CPU bound loop and how fast
+//! opcodes are decoded. Run with `cargo bench`.
+//!
+//! Typical best-of-5 results for 1000 frames of this loop, with a release
+//! build on a flat 64 KiB memory bus, before and after opcodes were decoded
+//! through the precomputed tables:
+//!
+//! - `from_byte` on every step: about 6100 frames/s (103x realtime)
+//! - `Instruction::decode`: about 9100 frames/s (152x realtime)
+//!
+//! Decoding alone takes about 8 ns per opcode with `from_byte` and its
+//! metadata, and under 1 ns with the table. The cartridge and the memory
+//! map added since then cost part of the gain, so the interpreter now runs
+//! at about 6100 frames/s again.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use dmg_01::assembler::assemble;
+use dmg_01::cpu::instruction::Instruction;
+use dmg_01::cpu::CPU;
+
+// mix of ALU, load, stack, jump and prefixed instructions
+const PROGRAM: &str = "
+    ld sp, $DFFF
+    ld hl, $C000
+loop:
+    ld a, [hl]
+    add a, b
+    xor c
+    ld [hl+], a
+    inc b
+    dec c
+    push bc
+    pop de
+    swap a
+    bit 3, a
+    rl e
+    cp $42
+    call nz, function
+    ld a, h
+    and $C1
+    ld h, a
+    jr loop
+function:
+    inc d
+    ret
+";
+
+fn run_cpu(cycles: u64) -> Duration {
+    let program = assemble(PROGRAM).unwrap();
+    let mut cpu = CPU::new();
+    cpu.bus.cartridge.rom_mut()[..program.len()].copy_from_slice(&program);
+
+    let start = Instant::now();
+    dmg_01::run_for_cycles(&mut cpu, cycles).unwrap();
+    black_box(&cpu);
+    start.elapsed()
+}
+
+fn decode_all(iterations: u32, decode: impl Fn(u8, bool)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        for prefixed in [false, true] {
+            for byte in 0..=0xFFu8 {
+                decode(black_box(byte), prefixed);
+            }
+        }
+    }
+    start.elapsed()
+}
+
+// best of a few runs, to reduce the noise from other processes
+fn best_of(runs: u32, measure: impl Fn() -> Duration) -> Duration {
+    (0..runs).map(|_| measure()).min().unwrap()
+}
+
+fn main() {
+    let frames = 1000;
+    let cycles = frames * dmg_01::CYCLES_PER_FRAME;
+    let elapsed = best_of(5, || run_cpu(cycles));
+    println!(
+        "interpreter: {} frames in {:?} ({:.0} frames/s, {:.1}x realtime)",
+        frames,
+        elapsed,
+        frames as f64 / elapsed.as_secs_f64(),
+        (cycles as f64 / dmg_01::CYCLES_PER_SECOND as f64) / elapsed.as_secs_f64()
+    );
+
+    let iterations = 100_000;
+    let per_opcode = |elapsed: Duration| elapsed.as_nanos() as f64 / (iterations as f64 * 512.0);
+    let elapsed = best_of(5, || {
+        decode_all(iterations, |byte, prefixed| {
+            let instruction = Instruction::from_byte(byte, prefixed);
+            black_box(instruction.map(|i| (i.size(), i.cycles(), i.branch_cycles())));
+        })
+    });
+    println!("from_byte + metadata: {:.2} ns/opcode", per_opcode(elapsed));
+    let elapsed = best_of(5, || {
+        decode_all(iterations, |byte, prefixed| {
+            black_box(Instruction::decode(byte, prefixed));
+        })
+    });
+    println!("decode table: {:.2} ns/opcode", per_opcode(elapsed));
+}
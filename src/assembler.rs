@@ -0,0 +1,934 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cpu::instruction::{
+    ADDHLTarget, ArithmeticTarget, BitPosition, IncDecTarget, Indirect, Instruction, JumpTest,
+    LoadByteSource, LoadByteTarget, LoadType, LoadWordTarget, PrefixTarget, RSTLocation,
+    StackTarget,
+};
+
+/// Error raised while assembling, `line` starts at 1
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Assembles RGBDS-style SM83 source into bytes, starting at address 0x0000
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_at(source, 0x0000)
+}
+
+/// Assembles RGBDS-style SM83 source into bytes placed at `origin`.
+///
+/// Supports every SM83 mnemonic, `label:` definitions (labels starting with
+/// a `.` are local to the previous global label), `db` and `dw` directives
+/// and `;` comments. Numbers can be written as `$FF`, `0xFF`, `%1010` or in
+/// decimal, and operands can add or subtract labels and numbers.
+pub fn assemble_at(source: &str, origin: u16) -> Result<Vec<u8>, AssembleError> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut scope = String::new();
+    let mut address = origin as u32;
+
+    // first pass: parse every line and give an address to each label
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| AssembleError { line, message };
+        let mut text = strip_comment(text).trim();
+
+        if let Some((label, rest)) = split_label(text) {
+            let name = if label.starts_with('.') {
+                format!("{}{}", scope, label)
+            } else {
+                scope = label.to_string();
+                label.to_string()
+            };
+            if labels.insert(name.clone(), address as i64).is_some() {
+                return Err(error(format!("label `{}` is defined twice", name)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(text, &scope).map_err(error)?;
+        address += statement.size() as u32;
+        if address > 0x10000 {
+            return Err(error(
+                "program doesn't fit in the address space".to_string(),
+            ));
+        }
+        statements.push((line, statement));
+    }
+
+    // second pass: resolve the operands now that every label is known
+    let mut bytes = Vec::new();
+    for (line, statement) in statements {
+        let address = origin.wrapping_add(bytes.len() as u16);
+        statement
+            .emit(address, &labels, &mut bytes)
+            .map_err(|message| AssembleError { line, message })?;
+    }
+    Ok(bytes)
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (index, character) in text.char_indices() {
+        match character {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn is_identifier_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_' || character == '.'
+}
+
+// splits `label: rest`, also accepting RGBDS exported labels (`label::`)
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let colon = text.find(':')?;
+    let label = &text[..colon];
+    if label.is_empty() || !label.chars().all(is_identifier_char) {
+        return None;
+    }
+    let rest = &text[colon + 1..];
+    Some((label, rest.strip_prefix(':').unwrap_or(rest)))
+}
+
+// splits operands on commas that are not inside a string
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut in_string = false;
+    let mut start = 0;
+    for (index, character) in text.char_indices() {
+        match character {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(text[start..].trim());
+    operands
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Number(i64),
+    Label(String),
+}
+
+/// Sum of numbers and labels, e.g. `label + 2`
+#[derive(Clone, Debug, PartialEq)]
+struct Expression {
+    terms: Vec<(bool, Term)>, // (negated, term)
+}
+
+impl Expression {
+    fn parse(text: &str, scope: &str) -> Result<Expression, String> {
+        let mut terms = Vec::new();
+        let mut negated = false;
+        let mut expect_term = true;
+        let mut rest = text.trim();
+
+        while !rest.is_empty() {
+            let character = rest.chars().next().unwrap();
+            if character.is_whitespace() {
+                rest = rest.trim_start();
+                continue;
+            }
+            if expect_term {
+                if character == '-' || character == '+' {
+                    negated ^= character == '-';
+                    rest = &rest[1..];
+                    continue;
+                }
+                let end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| !is_identifier_char(*c))
+                    .map(|(index, _)| index)
+                    .unwrap_or(rest.len());
+                let token = &rest[..end];
+                terms.push((negated, Expression::parse_term(token, scope)?));
+                rest = &rest[end..];
+                negated = false;
+                expect_term = false;
+            } else {
+                match character {
+                    '+' => negated = false,
+                    '-' => negated = true,
+                    _ => return Err(format!("invalid expression `{}`", text)),
+                }
+                rest = &rest[1..];
+                expect_term = true;
+            }
+        }
+
+        if expect_term {
+            return Err(format!("invalid expression `{}`", text));
+        }
+        Ok(Expression { terms })
+    }
+
+    fn parse_term(token: &str, scope: &str) -> Result<Term, String> {
+        let lowercase = token.to_ascii_lowercase();
+        let number = if let Some(digits) = lowercase.strip_prefix('$') {
+            i64::from_str_radix(digits, 16)
+        } else if let Some(digits) = lowercase.strip_prefix("0x") {
+            i64::from_str_radix(digits, 16)
+        } else if let Some(digits) = lowercase.strip_prefix('%') {
+            i64::from_str_radix(digits, 2)
+        } else if token.starts_with(|c: char| c.is_ascii_digit()) {
+            lowercase.parse()
+        } else if token.starts_with('.') {
+            return Ok(Term::Label(format!("{}{}", scope, token)));
+        } else if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return Ok(Term::Label(token.to_string()));
+        } else {
+            return Err(format!("invalid operand `{}`", token));
+        };
+        number
+            .map(Term::Number)
+            .map_err(|_| format!("invalid number `{}`", token))
+    }
+
+    fn number(value: i64) -> Expression {
+        Expression {
+            terms: vec![(false, Term::Number(value))],
+        }
+    }
+
+    // value of an expression that can't reference labels, like a bit number
+    fn constant(&self) -> Option<i64> {
+        self.evaluate(&HashMap::new()).ok()
+    }
+
+    fn evaluate(&self, labels: &HashMap<String, i64>) -> Result<i64, String> {
+        let mut value: i64 = 0;
+        for (negated, term) in &self.terms {
+            let term_value = match term {
+                Term::Number(number) => *number,
+                Term::Label(name) => *labels
+                    .get(name)
+                    .ok_or_else(|| format!("undefined label `{}`", name))?,
+            };
+            let term_value = if *negated {
+                term_value.checked_neg()
+            } else {
+                Some(term_value)
+            };
+            value = term_value
+                .and_then(|term_value| value.checked_add(term_value))
+                .ok_or_else(|| "expression overflows".to_string())?;
+        }
+        Ok(value)
+    }
+}
+
+/// How an immediate operand is encoded after the opcode
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Immediate {
+    Byte,         // n8
+    Word,         // n16 and a16
+    HighAddress,  // a8 of LDH, written either as $FFxx or $xx
+    RelativeJump, // e8 of JR, written as the target address
+    StackOffset,  // e8 of ADD SP and LD HL, SP+e8
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Statement {
+    Instruction(Instruction, Option<(Immediate, Expression)>),
+    Bytes(Vec<Expression>),
+    Words(Vec<Expression>),
+}
+
+impl Statement {
+    fn size(&self) -> u16 {
+        match self {
+            Statement::Instruction(instruction, _) => instruction.size(),
+            Statement::Bytes(values) => values.len() as u16,
+            Statement::Words(values) => values.len() as u16 * 2,
+        }
+    }
+
+    fn emit(
+        &self,
+        address: u16,
+        labels: &HashMap<String, i64>,
+        bytes: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        match self {
+            Statement::Instruction(instruction, immediate) => {
+                let mut operand = Vec::new();
+                if let Some((kind, expression)) = immediate {
+                    let value = expression.evaluate(labels)?;
+                    emit_immediate(*kind, value, address, &mut operand)?;
+                }
+                operand.resize(2, 0x00);
+                let encoded = instruction
+                    .encode(u16::from_le_bytes([operand[0], operand[1]]))
+                    .ok_or_else(|| format!("`{}` has no encoding", instruction))?;
+                bytes.extend(encoded);
+            }
+            Statement::Bytes(values) => {
+                for expression in values {
+                    let value = expression.evaluate(labels)?;
+                    emit_immediate(Immediate::Byte, value, address, bytes)?;
+                }
+            }
+            Statement::Words(values) => {
+                for expression in values {
+                    let value = expression.evaluate(labels)?;
+                    emit_immediate(Immediate::Word, value, address, bytes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn emit_immediate(
+    kind: Immediate,
+    value: i64,
+    address: u16,
+    bytes: &mut Vec<u8>,
+) -> Result<(), String> {
+    match kind {
+        Immediate::Byte if (-0x80..=0xFF).contains(&value) => bytes.push(value as u8),
+        Immediate::Word if (-0x8000..=0xFFFF).contains(&value) => {
+            bytes.extend((value as u16).to_le_bytes())
+        }
+        Immediate::HighAddress if (0x00..=0xFF).contains(&value) => bytes.push(value as u8),
+        Immediate::HighAddress if (0xFF00..=0xFFFF).contains(&value) => bytes.push(value as u8),
+        Immediate::RelativeJump => {
+            // the offset is relative to the end of the 2 bytes JR instruction
+            let offset = value - (address as i64 + 2);
+            if !(-0x80..=0x7F).contains(&offset) {
+                return Err(format!("relative jump to ${:04X} is out of range", value));
+            }
+            bytes.push(offset as u8)
+        }
+        Immediate::StackOffset if (-0x80..=0x7F).contains(&value) => bytes.push(value as u8),
+        _ => return Err(format!("value {} is out of range", value)),
+    }
+    Ok(())
+}
+
+/// Operand as written in the source, before knowing which instruction uses it
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    A,
+    B,
+    C, // register C or the carry condition
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    NotZero,
+    Zero,
+    NotCarry,
+    HLIndirect,      // [HL]
+    HLIndirectPlus,  // [HL+] or [HLI]
+    HLIndirectMinus, // [HL-] or [HLD]
+    BCIndirect,      // [BC]
+    DEIndirect,      // [DE]
+    CIndirect,       // [C] or [$FF00+C]
+    Memory(Expression),
+    StackOffset(Expression), // SP+e8
+    Value(Expression),
+}
+
+impl Operand {
+    fn parse(text: &str, scope: &str) -> Result<Operand, String> {
+        let compact: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        if let Some(inner) = text.trim().strip_prefix('[') {
+            let inner = inner
+                .strip_suffix(']')
+                .ok_or_else(|| format!("missing `]` in `{}`", text))?;
+            let operand = match &compact[1..compact.len() - 1] {
+                "hl" => Operand::HLIndirect,
+                "hl+" | "hli" => Operand::HLIndirectPlus,
+                "hl-" | "hld" => Operand::HLIndirectMinus,
+                "bc" => Operand::BCIndirect,
+                "de" => Operand::DEIndirect,
+                "c" | "$ff00+c" | "0xff00+c" => Operand::CIndirect,
+                _ => Operand::Memory(Expression::parse(inner, scope)?),
+            };
+            return Ok(operand);
+        }
+
+        let operand = match compact.as_str() {
+            "a" => Operand::A,
+            "b" => Operand::B,
+            "c" => Operand::C,
+            "d" => Operand::D,
+            "e" => Operand::E,
+            "h" => Operand::H,
+            "l" => Operand::L,
+            "af" => Operand::AF,
+            "bc" => Operand::BC,
+            "de" => Operand::DE,
+            "hl" => Operand::HL,
+            "sp" => Operand::SP,
+            "nz" => Operand::NotZero,
+            "z" => Operand::Zero,
+            "nc" => Operand::NotCarry,
+            _ if compact.starts_with("sp+") || compact.starts_with("sp-") => {
+                // the offset starts after the `p`, whitespace can come before it
+                let (index, p) = text
+                    .char_indices()
+                    .filter(|(_, c)| !c.is_whitespace())
+                    .nth(1)
+                    .unwrap();
+                Operand::StackOffset(Expression::parse(&text[index + p.len_utf8()..], scope)?)
+            }
+            _ => Operand::Value(Expression::parse(text, scope)?),
+        };
+        Ok(operand)
+    }
+
+    fn arithmetic_target(&self) -> Option<(ArithmeticTarget, Option<(Immediate, Expression)>)> {
+        let target = match self {
+            Operand::A => ArithmeticTarget::A,
+            Operand::B => ArithmeticTarget::B,
+            Operand::C => ArithmeticTarget::C,
+            Operand::D => ArithmeticTarget::D,
+            Operand::E => ArithmeticTarget::E,
+            Operand::H => ArithmeticTarget::H,
+            Operand::L => ArithmeticTarget::L,
+            Operand::HLIndirect => ArithmeticTarget::HLI,
+            Operand::Value(value) => {
+                return Some((ArithmeticTarget::D8, Some((Immediate::Byte, value.clone()))))
+            }
+            _ => return None,
+        };
+        Some((target, None))
+    }
+
+    fn inc_dec_target(&self) -> Option<IncDecTarget> {
+        let target = match self {
+            Operand::A => IncDecTarget::A,
+            Operand::B => IncDecTarget::B,
+            Operand::C => IncDecTarget::C,
+            Operand::D => IncDecTarget::D,
+            Operand::E => IncDecTarget::E,
+            Operand::H => IncDecTarget::H,
+            Operand::L => IncDecTarget::L,
+            Operand::HLIndirect => IncDecTarget::HLI,
+            Operand::BC => IncDecTarget::BC,
+            Operand::DE => IncDecTarget::DE,
+            Operand::HL => IncDecTarget::HL,
+            Operand::SP => IncDecTarget::SP,
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    fn prefix_target(&self) -> Option<PrefixTarget> {
+        let target = match self {
+            Operand::A => PrefixTarget::A,
+            Operand::B => PrefixTarget::B,
+            Operand::C => PrefixTarget::C,
+            Operand::D => PrefixTarget::D,
+            Operand::E => PrefixTarget::E,
+            Operand::H => PrefixTarget::H,
+            Operand::L => PrefixTarget::L,
+            Operand::HLIndirect => PrefixTarget::HLI,
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    fn load_byte_target(&self) -> Option<LoadByteTarget> {
+        let target = match self {
+            Operand::A => LoadByteTarget::A,
+            Operand::B => LoadByteTarget::B,
+            Operand::C => LoadByteTarget::C,
+            Operand::D => LoadByteTarget::D,
+            Operand::E => LoadByteTarget::E,
+            Operand::H => LoadByteTarget::H,
+            Operand::L => LoadByteTarget::L,
+            Operand::HLIndirect => LoadByteTarget::HLI,
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    fn load_byte_source(&self) -> Option<LoadByteSource> {
+        let source = match self {
+            Operand::A => LoadByteSource::A,
+            Operand::B => LoadByteSource::B,
+            Operand::C => LoadByteSource::C,
+            Operand::D => LoadByteSource::D,
+            Operand::E => LoadByteSource::E,
+            Operand::H => LoadByteSource::H,
+            Operand::L => LoadByteSource::L,
+            Operand::HLIndirect => LoadByteSource::HLI,
+            Operand::Value(_) => LoadByteSource::D8,
+            _ => return None,
+        };
+        Some(source)
+    }
+
+    fn load_word_target(&self) -> Option<LoadWordTarget> {
+        let target = match self {
+            Operand::BC => LoadWordTarget::BC,
+            Operand::DE => LoadWordTarget::DE,
+            Operand::HL => LoadWordTarget::HL,
+            Operand::SP => LoadWordTarget::SP,
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    // indirect operands loaded into or from A with a one byte LD
+    fn indirect(&self) -> Option<Indirect> {
+        let indirect = match self {
+            Operand::BCIndirect => Indirect::BCIndirect,
+            Operand::DEIndirect => Indirect::DEIndirect,
+            Operand::HLIndirectPlus => Indirect::HLIndirectPlus,
+            Operand::HLIndirectMinus => Indirect::HLIndirectMinus,
+            Operand::CIndirect => Indirect::LastByteIndirect,
+            _ => return None,
+        };
+        Some(indirect)
+    }
+
+    fn stack_target(&self) -> Option<StackTarget> {
+        let target = match self {
+            Operand::AF => StackTarget::AF,
+            Operand::BC => StackTarget::BC,
+            Operand::DE => StackTarget::DE,
+            Operand::HL => StackTarget::HL,
+            _ => return None,
+        };
+        Some(target)
+    }
+
+    fn condition(&self) -> Option<JumpTest> {
+        let condition = match self {
+            Operand::NotZero => JumpTest::NotZero,
+            Operand::Zero => JumpTest::Zero,
+            Operand::NotCarry => JumpTest::NotCarry,
+            Operand::C => JumpTest::Carry,
+            _ => return None,
+        };
+        Some(condition)
+    }
+
+    fn bit_position(&self) -> Option<BitPosition> {
+        let position = match self {
+            Operand::Value(value) => match value.constant()? {
+                0 => BitPosition::B0,
+                1 => BitPosition::B1,
+                2 => BitPosition::B2,
+                3 => BitPosition::B3,
+                4 => BitPosition::B4,
+                5 => BitPosition::B5,
+                6 => BitPosition::B6,
+                7 => BitPosition::B7,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(position)
+    }
+
+    fn rst_location(&self) -> Option<RSTLocation> {
+        let location = match self {
+            Operand::Value(value) => match value.constant()? {
+                0x00 => RSTLocation::X00,
+                0x08 => RSTLocation::X08,
+                0x10 => RSTLocation::X10,
+                0x18 => RSTLocation::X18,
+                0x20 => RSTLocation::X20,
+                0x28 => RSTLocation::X28,
+                0x30 => RSTLocation::X30,
+                0x38 => RSTLocation::X38,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(location)
+    }
+}
+
+fn parse_statement(text: &str, scope: &str) -> Result<Statement, String> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+    let mnemonic = mnemonic.to_ascii_lowercase();
+
+    match mnemonic.as_str() {
+        "db" => return parse_data(rest, scope).map(Statement::Bytes),
+        "dw" => return parse_data(rest, scope).map(Statement::Words),
+        _ => {}
+    }
+
+    let operands = if rest.is_empty() {
+        Vec::new()
+    } else {
+        split_operands(rest)
+            .into_iter()
+            .map(|operand| Operand::parse(operand, scope))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    parse_instruction(&mnemonic, &operands).ok_or_else(|| {
+        if is_mnemonic(&mnemonic) {
+            format!("invalid operands for `{}`: `{}`", mnemonic, rest)
+        } else {
+            format!("unknown mnemonic `{}`", mnemonic)
+        }
+    })
+}
+
+fn parse_data(text: &str, scope: &str) -> Result<Vec<Expression>, String> {
+    let mut values = Vec::new();
+    for item in split_operands(text) {
+        if let Some(string) = item.strip_prefix('"') {
+            let string = string
+                .strip_suffix('"')
+                .ok_or_else(|| format!("unterminated string `{}`", item))?;
+            values.extend(string.bytes().map(|byte| Expression::number(byte as i64)));
+        } else {
+            values.push(Expression::parse(item, scope)?);
+        }
+    }
+    Ok(values)
+}
+
+fn is_mnemonic(mnemonic: &str) -> bool {
+    const MNEMONICS: [&str; 46] = [
+        "adc", "add", "and", "bit", "call", "ccf", "cp", "cpl", "daa", "dec", "di", "ei", "halt",
+        "inc", "jp", "jr", "ld", "ldd", "ldh", "ldi", "nop", "or", "pop", "push", "res", "ret",
+        "reti", "rl", "rla", "rlc", "rlca", "rr", "rra", "rrc", "rrca", "rst", "sbc", "scf", "set",
+        "sla", "sra", "srl", "stop", "sub", "swap", "xor",
+    ];
+    MNEMONICS.contains(&mnemonic)
+}
+
+type Parsed = (Instruction, Option<(Immediate, Expression)>);
+
+fn parse_instruction(mnemonic: &str, operands: &[Operand]) -> Option<Statement> {
+    let (instruction, immediate): Parsed = match (mnemonic, operands) {
+        ("add", [Operand::HL, source]) => {
+            let target = match source {
+                Operand::BC => ADDHLTarget::BC,
+                Operand::DE => ADDHLTarget::DE,
+                Operand::HL => ADDHLTarget::HL,
+                Operand::SP => ADDHLTarget::SP,
+                _ => return None,
+            };
+            (Instruction::ADDHL(target), None)
+        }
+        ("add", [Operand::SP, Operand::Value(offset)]) => (
+            Instruction::ADDSP,
+            Some((Immediate::StackOffset, offset.clone())),
+        ),
+        ("add" | "adc" | "sub" | "sbc" | "and" | "xor" | "or" | "cp", [Operand::A, source])
+        | ("add" | "adc" | "sub" | "sbc" | "and" | "xor" | "or" | "cp", [source]) => {
+            let (target, immediate) = source.arithmetic_target()?;
+            let instruction = match mnemonic {
+                "add" => Instruction::ADD(target),
+                "adc" => Instruction::ADC(target),
+                "sub" => Instruction::SUB(target),
+                "sbc" => Instruction::SBC(target),
+                "and" => Instruction::AND(target),
+                "xor" => Instruction::XOR(target),
+                "or" => Instruction::OR(target),
+                _ => Instruction::CP(target),
+            };
+            (instruction, immediate)
+        }
+        ("inc", [target]) => (Instruction::INC(target.inc_dec_target()?), None),
+        ("dec", [target]) => (Instruction::DEC(target.inc_dec_target()?), None),
+
+        ("ld", [target, source]) => parse_load(target, source)?,
+        ("ldi", [Operand::A, Operand::HLIndirect]) => (
+            Instruction::LD(LoadType::AFromIndirect(Indirect::HLIndirectPlus)),
+            None,
+        ),
+        ("ldi", [Operand::HLIndirect, Operand::A]) => (
+            Instruction::LD(LoadType::IndirectFromA(Indirect::HLIndirectPlus)),
+            None,
+        ),
+        ("ldd", [Operand::A, Operand::HLIndirect]) => (
+            Instruction::LD(LoadType::AFromIndirect(Indirect::HLIndirectMinus)),
+            None,
+        ),
+        ("ldd", [Operand::HLIndirect, Operand::A]) => (
+            Instruction::LD(LoadType::IndirectFromA(Indirect::HLIndirectMinus)),
+            None,
+        ),
+        ("ldh", [Operand::A, Operand::CIndirect]) => (
+            Instruction::LD(LoadType::AFromIndirect(Indirect::LastByteIndirect)),
+            None,
+        ),
+        ("ldh", [Operand::CIndirect, Operand::A]) => (
+            Instruction::LD(LoadType::IndirectFromA(Indirect::LastByteIndirect)),
+            None,
+        ),
+        ("ldh", [Operand::A, Operand::Memory(address)]) => (
+            Instruction::LD(LoadType::AFromByteAddress),
+            Some((Immediate::HighAddress, address.clone())),
+        ),
+        ("ldh", [Operand::Memory(address), Operand::A]) => (
+            Instruction::LD(LoadType::ByteAddressFromA),
+            Some((Immediate::HighAddress, address.clone())),
+        ),
+
+        ("jp", [Operand::HL | Operand::HLIndirect]) => (Instruction::JPI, None),
+        ("jp", [Operand::Value(address)]) => (
+            Instruction::JP(JumpTest::Always),
+            Some((Immediate::Word, address.clone())),
+        ),
+        ("jp", [condition, Operand::Value(address)]) => (
+            Instruction::JP(condition.condition()?),
+            Some((Immediate::Word, address.clone())),
+        ),
+        ("jr", [Operand::Value(address)]) => (
+            Instruction::JR(JumpTest::Always),
+            Some((Immediate::RelativeJump, address.clone())),
+        ),
+        ("jr", [condition, Operand::Value(address)]) => (
+            Instruction::JR(condition.condition()?),
+            Some((Immediate::RelativeJump, address.clone())),
+        ),
+        ("call", [Operand::Value(address)]) => (
+            Instruction::CALL(JumpTest::Always),
+            Some((Immediate::Word, address.clone())),
+        ),
+        ("call", [condition, Operand::Value(address)]) => (
+            Instruction::CALL(condition.condition()?),
+            Some((Immediate::Word, address.clone())),
+        ),
+        ("ret", []) => (Instruction::RET(JumpTest::Always), None),
+        ("ret", [condition]) => (Instruction::RET(condition.condition()?), None),
+        ("reti", []) => (Instruction::RETI, None),
+        ("rst", [location]) => (Instruction::RST(location.rst_location()?), None),
+        ("push", [target]) => (Instruction::PUSH(target.stack_target()?), None),
+        ("pop", [target]) => (Instruction::POP(target.stack_target()?), None),
+
+        ("bit", [position, target]) => (
+            Instruction::BIT(target.prefix_target()?, position.bit_position()?),
+            None,
+        ),
+        ("res", [position, target]) => (
+            Instruction::RES(target.prefix_target()?, position.bit_position()?),
+            None,
+        ),
+        ("set", [position, target]) => (
+            Instruction::SET(target.prefix_target()?, position.bit_position()?),
+            None,
+        ),
+        ("srl", [target]) => (Instruction::SRL(target.prefix_target()?), None),
+        ("rr", [target]) => (Instruction::RR(target.prefix_target()?), None),
+        ("rl", [target]) => (Instruction::RL(target.prefix_target()?), None),
+        ("rrc", [target]) => (Instruction::RRC(target.prefix_target()?), None),
+        ("rlc", [target]) => (Instruction::RLC(target.prefix_target()?), None),
+        ("sra", [target]) => (Instruction::SRA(target.prefix_target()?), None),
+        ("sla", [target]) => (Instruction::SLA(target.prefix_target()?), None),
+        ("swap", [target]) => (Instruction::SWAP(target.prefix_target()?), None),
+
+        ("daa", []) => (Instruction::DAA, None),
+        ("cpl", []) => (Instruction::CPL, None),
+        ("ccf", []) => (Instruction::CCF, None),
+        ("scf", []) => (Instruction::SCF, None),
+        ("rra", []) => (Instruction::RRA, None),
+        ("rla", []) => (Instruction::RLA, None),
+        ("rrca", []) => (Instruction::RRCA, None),
+        ("rlca", []) => (Instruction::RLCA, None),
+        ("halt", []) => (Instruction::HALT, None),
+        ("stop", []) => (Instruction::STOP, None),
+        ("nop", []) => (Instruction::NOP, None),
+        ("di", []) => (Instruction::DI, None),
+        ("ei", []) => (Instruction::EI, None),
+        _ => return None,
+    };
+    Some(Statement::Instruction(instruction, immediate))
+}
+
+fn parse_load(target: &Operand, source: &Operand) -> Option<Parsed> {
+    let load = match (target, source) {
+        // LD [HL], [HL] is the encoding of HALT
+        (Operand::HLIndirect, Operand::HLIndirect) => return None,
+        (Operand::SP, Operand::HL) => (LoadType::SPFromHL, None),
+        (Operand::HL, Operand::StackOffset(offset)) => (
+            LoadType::HLFromSPN,
+            Some((Immediate::StackOffset, offset.clone())),
+        ),
+        (Operand::Memory(address), Operand::SP) => (
+            LoadType::IndirectFromSP,
+            Some((Immediate::Word, address.clone())),
+        ),
+        (Operand::A, Operand::Memory(address)) => (
+            LoadType::AFromIndirect(Indirect::WordIndirect),
+            Some((Immediate::Word, address.clone())),
+        ),
+        (Operand::Memory(address), Operand::A) => (
+            LoadType::IndirectFromA(Indirect::WordIndirect),
+            Some((Immediate::Word, address.clone())),
+        ),
+        (Operand::A, indirect) if indirect.indirect().is_some() => {
+            (LoadType::AFromIndirect(indirect.indirect()?), None)
+        }
+        (indirect, Operand::A) if indirect.indirect().is_some() => {
+            (LoadType::IndirectFromA(indirect.indirect()?), None)
+        }
+        (target, Operand::Value(value)) if target.load_word_target().is_some() => (
+            LoadType::Word(target.load_word_target()?),
+            Some((Immediate::Word, value.clone())),
+        ),
+        (target, source) => {
+            let load = LoadType::Byte(target.load_byte_target()?, source.load_byte_source()?);
+            let immediate = match source {
+                Operand::Value(value) => Some((Immediate::Byte, value.clone())),
+                _ => None,
+            };
+            (load, immediate)
+        }
+    };
+    Some((Instruction::LD(load.0), load.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn assemble_instructions() {
+        let program = assemble(
+            "
+            ld sp, $FFFE      ; set up the stack
+            xor a
+            ld [hl+], a
+            ldh [$FF40], a
+            ld a, [c]
+            bit 7, h
+            add hl, de
+            ld hl, sp-2
+            stop
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![
+                0x31, 0xFE, 0xFF, 0xAF, 0x22, 0xE0, 0x40, 0xF2, 0xCB, 0x7C, 0x19, 0xF8, 0xFE, 0x10,
+                0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_and_relative_jumps() {
+        let program = assemble_at(
+            "
+            Start:
+                ld b, 3
+            .loop: dec b
+                jr nz, .loop
+                jp Start
+            ",
+            0x0150,
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![0x06, 0x03, 0x05, 0x20, 0xFD, 0xC3, 0x50, 0x01]
+        );
+    }
+
+    #[test]
+    fn data_directives() {
+        let program = assemble("db $01, \"Hi\", -1\ndw $1234, end\nend:").unwrap();
+        assert_eq!(
+            program,
+            vec![0x01, b'H', b'i', 0xFF, 0x34, 0x12, 0x08, 0x00]
+        );
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        assert_eq!(
+            assemble("nop\nld a, [hl], b"),
+            Err(AssembleError {
+                line: 2,
+                message: "invalid operands for `ld`: `a, [hl], b`".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("jp nowhere").unwrap_err().message,
+            "undefined label `nowhere`"
+        );
+        assert_eq!(
+            assemble("frobnicate").unwrap_err().message,
+            "unknown mnemonic `frobnicate`"
+        );
+        assert_eq!(
+            assemble("ld b, 256").unwrap_err().message,
+            "value 256 is out of range"
+        );
+        assert_eq!(
+            assemble("dw $7FFFFFFFFFFFFFFF + 1").unwrap_err().message,
+            "expression overflows"
+        );
+    }
+
+    #[test]
+    fn stack_offset_allows_any_whitespace() {
+        assert_eq!(assemble("ld hl, s\u{3000}p + 2"), assemble("ld hl, sp+2"));
+    }
+
+    #[test]
+    fn assembled_program_runs() {
+        let program = assemble(
+            "
+                ld a, 5
+                ld b, 0
+            loop:
+                inc b
+                dec a
+                jr nz, loop
+                halt
+            ",
+        )
+        .unwrap();
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[..program.len()].copy_from_slice(&program);
+        while !cpu.is_halted {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.b, 5);
+    }
+}
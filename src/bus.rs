@@ -0,0 +1,174 @@
+use crate::interrupt::Interrupt;
+
+/// Memory and peripherals the CPU runs against. `MemoryBus` is the full
+/// system, `FlatBus` and `RecordingBus` are meant for testing instructions.
+pub trait Bus {
+    /// Reads a byte for the CPU, which may have side effects on the hardware
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// Advances the components on the bus by `cycles` CPU T-cycles
+    fn tick(&mut self, cycles: u8);
+    /// Reads a byte without side effects, for tracers and debuggers
+    fn peek(&self, address: u16) -> u8;
+
+    /// Called for M-cycles where the CPU doesn't access the bus
+    fn idle(&mut self) {}
+
+    /// Highest priority interrupt that is both requested and enabled
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        None
+    }
+
+    /// Clears the request of `interrupt` once the CPU has serviced it
+    fn acknowledge_interrupt(&mut self, _interrupt: Interrupt) {}
+
+    /// Called by STOP. Returns true when the bus performed a CGB speed switch
+    /// instead of letting the CPU enter STOP mode.
+    fn stop(&mut self) -> bool {
+        false
+    }
+
+    /// Whether the CPU runs at twice the normal clock speed
+    fn double_speed(&self) -> bool {
+        false
+    }
+}
+
+/// One M-cycle of bus activity performed by the CPU
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BusAccess {
+    Read { address: u16, value: u8 },
+    Write { address: u16, value: u8 },
+    Idle, // internal cycle without memory access
+}
+
+/// 64 KiB of plain RAM without any peripherals, interrupts are never
+/// requested.
+pub struct FlatBus {
+    pub memory: Box<[u8; 0x10000]>,
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatBus {
+    pub fn new() -> Self {
+        Self {
+            memory: Box::new([0x0; 0x10000]),
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn tick(&mut self, _cycles: u8) {}
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+}
+
+/// Wraps another bus and logs every access the CPU makes through it
+pub struct RecordingBus<B> {
+    pub inner: B,
+    pub accesses: Vec<BusAccess>,
+}
+
+impl<B: Bus> RecordingBus<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            accesses: vec![],
+        }
+    }
+}
+
+impl<B: Bus> Bus for RecordingBus<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.inner.read(address);
+        self.accesses.push(BusAccess::Read { address, value });
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.accesses.push(BusAccess::Write { address, value });
+        self.inner.write(address, value);
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.inner.tick(cycles);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.inner.peek(address)
+    }
+
+    fn idle(&mut self) {
+        self.accesses.push(BusAccess::Idle);
+        self.inner.idle();
+    }
+
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        self.inner.pending_interrupt()
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.inner.acknowledge_interrupt(interrupt);
+    }
+
+    fn stop(&mut self) -> bool {
+        self.inner.stop()
+    }
+
+    fn double_speed(&self) -> bool {
+        self.inner.double_speed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_bus_is_plain_ram() {
+        let mut bus = FlatBus::new();
+        bus.write(0x0000, 0x12);
+        bus.write(0xFFFF, 0x34);
+        assert_eq!(bus.read(0x0000), 0x12);
+        assert_eq!(bus.peek(0xFFFF), 0x34);
+        assert_eq!(bus.pending_interrupt(), None);
+    }
+
+    #[test]
+    fn recording_bus_logs_reads_and_writes() {
+        let mut bus = RecordingBus::new(FlatBus::new());
+        bus.write(0xC000, 0x12);
+        bus.idle();
+        assert_eq!(bus.read(0xC000), 0x12);
+        assert_eq!(bus.peek(0xC000), 0x12);
+        assert_eq!(
+            bus.accesses,
+            vec![
+                BusAccess::Write {
+                    address: 0xC000,
+                    value: 0x12
+                },
+                BusAccess::Idle,
+                BusAccess::Read {
+                    address: 0xC000,
+                    value: 0x12
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,663 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::mapper::{Mapper, RTCClock, RomOnly, MBC1, MBC2, MBC3, MBC5, MBC7, RTC};
+use crate::model::Model;
+
+const HEADER_END: usize = 0x0150;
+const TITLE_ADDRESS: usize = 0x0134;
+const NEW_LICENSEE_ADDRESS: usize = 0x0144;
+const CGB_FLAG_ADDRESS: usize = 0x0143;
+const SGB_FLAG_ADDRESS: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const ROM_SIZE_ADDRESS: usize = 0x0148;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+const OLD_LICENSEE_ADDRESS: usize = 0x014B;
+const VERSION_ADDRESS: usize = 0x014C;
+const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+const GLOBAL_CHECKSUM_ADDRESS: usize = 0x014E;
+
+/// Reasons a ROM image can't be loaded
+#[derive(Clone, Debug, PartialEq)]
+pub enum CartridgeError {
+    // the image ends before the header or before the size given in it
+    Truncated { expected: usize, actual: usize },
+    UnknownCartridgeType(u8),
+    UnsupportedCartridgeType(CartridgeType),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    HeaderChecksum { expected: u8, actual: u8 },
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Truncated { expected, actual } => write!(
+                f,
+                "truncated ROM image: expected {} bytes, found {}",
+                expected, actual
+            ),
+            CartridgeError::UnknownCartridgeType(code) => {
+                write!(f, "unknown cartridge type 0x{:02x}", code)
+            }
+            CartridgeError::UnsupportedCartridgeType(cartridge_type) => write!(
+                f,
+                "unsupported cartridge type {} (0x{:02x})",
+                cartridge_type, cartridge_type.code
+            ),
+            CartridgeError::UnknownRomSize(code) => write!(f, "unknown ROM size 0x{:02x}", code),
+            CartridgeError::UnknownRamSize(code) => write!(f, "unknown RAM size 0x{:02x}", code),
+            CartridgeError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "bad header checksum: header says 0x{:02x}, computed 0x{:02x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// Problems in a ROM image that don't stop it from running. The boot ROM
+/// never checks the global checksum, and many homebrew and patched images
+/// get it wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CartridgeWarning {
+    GlobalChecksum { expected: u16, actual: u16 },
+    // the image is padded past the size in the header, the rest is dropped
+    Overdump { declared: usize, actual: usize },
+}
+
+impl fmt::Display for CartridgeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeWarning::GlobalChecksum { expected, actual } => write!(
+                f,
+                "bad global checksum: header says 0x{:04x}, computed 0x{:04x}",
+                expected, actual
+            ),
+            CartridgeWarning::Overdump { declared, actual } => write!(
+                f,
+                "ROM image is {} bytes but its header declares {}, the rest is ignored",
+                actual, declared
+            ),
+        }
+    }
+}
+
+/// Memory bank controller, or other hardware, handling the cartridge address space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MapperType {
+    RomOnly,
+    MBC1,
+    MBC2,
+    MMM01,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    PocketCamera,
+    TAMA5,
+    HuC3,
+    HuC1,
+}
+
+/// Hardware of the cartridge, decoded from the type code at 0x0147
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: MapperType,
+    pub ram: bool,
+    pub battery: bool, //RAM, and the clock, keep their contents when powered off
+    pub timer: bool,   //MBC3 real time clock
+    pub rumble: bool,
+    pub sensor: bool, //MBC7 accelerometer
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<CartridgeType> {
+        // mapper, RAM, battery, timer, rumble, sensor
+        let (mapper, ram, battery, timer, rumble, sensor) = match code {
+            0x00 => (MapperType::RomOnly, false, false, false, false, false),
+            0x01 => (MapperType::MBC1, false, false, false, false, false),
+            0x02 => (MapperType::MBC1, true, false, false, false, false),
+            0x03 => (MapperType::MBC1, true, true, false, false, false),
+            0x05 => (MapperType::MBC2, false, false, false, false, false),
+            0x06 => (MapperType::MBC2, false, true, false, false, false),
+            0x08 => (MapperType::RomOnly, true, false, false, false, false),
+            0x09 => (MapperType::RomOnly, true, true, false, false, false),
+            0x0B => (MapperType::MMM01, false, false, false, false, false),
+            0x0C => (MapperType::MMM01, true, false, false, false, false),
+            0x0D => (MapperType::MMM01, true, true, false, false, false),
+            0x0F => (MapperType::MBC3, false, true, true, false, false),
+            0x10 => (MapperType::MBC3, true, true, true, false, false),
+            0x11 => (MapperType::MBC3, false, false, false, false, false),
+            0x12 => (MapperType::MBC3, true, false, false, false, false),
+            0x13 => (MapperType::MBC3, true, true, false, false, false),
+            0x19 => (MapperType::MBC5, false, false, false, false, false),
+            0x1A => (MapperType::MBC5, true, false, false, false, false),
+            0x1B => (MapperType::MBC5, true, true, false, false, false),
+            0x1C => (MapperType::MBC5, false, false, false, true, false),
+            0x1D => (MapperType::MBC5, true, false, false, true, false),
+            0x1E => (MapperType::MBC5, true, true, false, true, false),
+            0x20 => (MapperType::MBC6, false, false, false, false, false),
+            0x22 => (MapperType::MBC7, true, true, false, true, true),
+            0xFC => (MapperType::PocketCamera, false, false, false, false, false),
+            0xFD => (MapperType::TAMA5, false, false, false, false, false),
+            0xFE => (MapperType::HuC3, false, false, false, false, false),
+            0xFF => (MapperType::HuC1, true, true, false, false, false),
+            _ => return None,
+        };
+        Some(CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+            sensor,
+        })
+    }
+
+    /// Whether the emulator implements the hardware of this cartridge
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self.mapper,
+            MapperType::RomOnly
+                | MapperType::MBC1
+                | MapperType::MBC2
+                | MapperType::MBC3
+                | MapperType::MBC5
+                | MapperType::MBC7
+        )
+    }
+}
+
+impl fmt::Display for CartridgeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mapper = match self.mapper {
+            MapperType::RomOnly => "ROM",
+            MapperType::MBC1 => "MBC1",
+            MapperType::MBC2 => "MBC2",
+            MapperType::MMM01 => "MMM01",
+            MapperType::MBC3 => "MBC3",
+            MapperType::MBC5 => "MBC5",
+            MapperType::MBC6 => "MBC6",
+            MapperType::MBC7 => "MBC7",
+            MapperType::PocketCamera => "POCKET CAMERA",
+            MapperType::TAMA5 => "TAMA5",
+            MapperType::HuC3 => "HuC3",
+            MapperType::HuC1 => "HuC1",
+        };
+        write!(f, "{}", mapper)?;
+        let features = [
+            (self.sensor, "SENSOR"),
+            (self.timer, "TIMER"),
+            (self.rumble, "RUMBLE"),
+            (self.ram, "RAM"),
+            (self.battery, "BATTERY"),
+        ];
+        for (present, name) in features {
+            if present {
+                write!(f, "+{}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Color support declared by the CGB flag at 0x0143
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CGBSupport {
+    None,
+    Compatible, // runs on both the DMG and the CGB
+    Only,
+}
+
+/// Publisher of the game. The old code at 0x014B is replaced by the two
+/// ASCII characters at 0x0144 when it is 0x33.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    New(String),
+}
+
+impl fmt::Display for Licensee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Licensee::Old(code) => write!(f, "{:02X}", code),
+            Licensee::New(code) => write!(f, "{}", code),
+        }
+    }
+}
+
+/// Cartridge header stored at 0x0100-0x014F
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub cgb_support: CGBSupport,
+    pub sgb_support: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize, //bytes
+    pub ram_size: usize, //bytes of external RAM, MBC2 RAM is built into the controller
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl Header {
+    /// Parses the header of `rom`, which must be at least 0x0150 bytes long.
+    /// Checksums aren't verified, see `Cartridge::from_bytes`.
+    pub fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated {
+                expected: HEADER_END,
+                actual: rom.len(),
+            });
+        }
+
+        let cgb_support = match rom[CGB_FLAG_ADDRESS] {
+            0xC0 => CGBSupport::Only,
+            flag if flag & 0x80 != 0 => CGBSupport::Compatible,
+            _ => CGBSupport::None,
+        };
+        // CGB games use the last byte of the title for the CGB flag
+        let title_end = match cgb_support {
+            CGBSupport::None => CGB_FLAG_ADDRESS + 1,
+            _ => CGB_FLAG_ADDRESS,
+        };
+        let title: String = rom[TITLE_ADDRESS..title_end]
+            .iter()
+            .take_while(|&&byte| byte != 0x00)
+            .map(|&byte| byte as char)
+            .collect();
+
+        let code = rom[CARTRIDGE_TYPE_ADDRESS];
+        let cartridge_type =
+            CartridgeType::from_code(code).ok_or(CartridgeError::UnknownCartridgeType(code))?;
+        let rom_size = match rom[ROM_SIZE_ADDRESS] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(CartridgeError::UnknownRomSize(code)),
+        };
+        let ram_size = match rom[RAM_SIZE_ADDRESS] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartridgeError::UnknownRamSize(code)),
+        };
+        let licensee = match rom[OLD_LICENSEE_ADDRESS] {
+            0x33 => Licensee::New(
+                rom[NEW_LICENSEE_ADDRESS..NEW_LICENSEE_ADDRESS + 2]
+                    .iter()
+                    .map(|&byte| byte as char)
+                    .collect(),
+            ),
+            code => Licensee::Old(code),
+        };
+
+        Ok(Header {
+            title,
+            cgb_support,
+            sgb_support: rom[SGB_FLAG_ADDRESS] == 0x03,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            licensee,
+            version: rom[VERSION_ADDRESS],
+            header_checksum: rom[HEADER_CHECKSUM_ADDRESS],
+            global_checksum: u16::from_be_bytes([
+                rom[GLOBAL_CHECKSUM_ADDRESS],
+                rom[GLOBAL_CHECKSUM_ADDRESS + 1],
+            ]),
+        })
+    }
+
+    /// Hardware to run the cartridge on: the CGB for CGB only games, the
+    /// DMG otherwise. Games that also support the CGB run in their DMG mode.
+    pub fn model(&self) -> Model {
+        match self.cgb_support {
+            CGBSupport::Only => Model::CGB,
+            _ => Model::DMG,
+        }
+    }
+}
+
+/// Checksum of the header bytes 0x0134-0x014C verified by the boot ROM
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_ADDRESS..HEADER_CHECKSUM_ADDRESS]
+        .iter()
+        .fold(0u8, |checksum, &byte| {
+            checksum.wrapping_sub(byte).wrapping_sub(1)
+        })
+}
+
+/// Sum of every byte of the ROM except the global checksum itself
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| !matches!(address, 0x014E | 0x014F))
+        .fold(0u16, |checksum, (_, &byte)| {
+            checksum.wrapping_add(byte as u16)
+        })
+}
+
+/// ROM image of a game cartridge together with its parsed header
+pub struct Cartridge {
+    pub header: Header,
+    pub rom: Vec<u8>,
+    pub rtc_clock: RTCClock, //time source of the MBC3 RTC, the host clock by default
+    pub warnings: Vec<CartridgeWarning>,
+}
+
+impl Cartridge {
+    /// Reads a .gb or .gbc file. Invalid images are reported as
+    /// `io::ErrorKind::InvalidData` errors wrapping a `CartridgeError`.
+    pub fn open(path: &Path) -> io::Result<Cartridge> {
+        let rom = fs::read(path)?;
+        Cartridge::from_bytes(rom)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Parses and validates a ROM image: it has to be at least as large as
+    /// the header declares, the header checksum has to be correct and the
+    /// cartridge hardware has to be supported. Problems the boot ROM
+    /// doesn't check are reported in `warnings`.
+    pub fn from_bytes(mut rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        let header = Header::parse(&rom)?;
+        let mut warnings = vec![];
+        if rom.len() < header.rom_size {
+            return Err(CartridgeError::Truncated {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+        if rom.len() > header.rom_size {
+            warnings.push(CartridgeWarning::Overdump {
+                declared: header.rom_size,
+                actual: rom.len(),
+            });
+            rom.truncate(header.rom_size);
+        }
+
+        let actual = header_checksum(&rom);
+        if actual != header.header_checksum {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: header.header_checksum,
+                actual,
+            });
+        }
+        let actual = global_checksum(&rom);
+        if actual != header.global_checksum {
+            warnings.push(CartridgeWarning::GlobalChecksum {
+                expected: header.global_checksum,
+                actual,
+            });
+        }
+
+        if !header.cartridge_type.is_supported() {
+            return Err(CartridgeError::UnsupportedCartridgeType(
+                header.cartridge_type,
+            ));
+        }
+        Ok(Cartridge {
+            header,
+            rom,
+            rtc_clock: RTCClock::Host,
+            warnings,
+        })
+    }
+
+    /// Creates the hardware the cartridge plugs into the bus, selected by
+    /// the cartridge type in the header
+    pub fn into_mapper(self) -> Box<dyn Mapper> {
+        let cartridge_type = self.header.cartridge_type;
+        let ram_size = if cartridge_type.ram {
+            self.header.ram_size
+        } else {
+            0
+        };
+        let battery = cartridge_type.battery;
+        match cartridge_type.mapper {
+            MapperType::MBC1 => {
+                let multicart = MBC1::is_multicart(&self.rom);
+                let mut mapper = MBC1::new(self.rom, ram_size, multicart);
+                mapper.battery = battery;
+                Box::new(mapper)
+            }
+            // the RAM is built into the controller, the header declares none
+            MapperType::MBC2 => {
+                let mut mapper = MBC2::new(self.rom);
+                mapper.battery = battery;
+                Box::new(mapper)
+            }
+            MapperType::MBC3 => {
+                let rtc = cartridge_type.timer.then(|| RTC::new(self.rtc_clock));
+                let mut mapper = MBC3::new(self.rom, ram_size, rtc);
+                mapper.battery = battery;
+                Box::new(mapper)
+            }
+            MapperType::MBC5 => {
+                let mut mapper = MBC5::new(self.rom, ram_size, cartridge_type.rumble);
+                mapper.battery = battery;
+                Box::new(mapper)
+            }
+            // the EEPROM replaces the RAM
+            MapperType::MBC7 => Box::new(MBC7::new(self.rom)),
+            _ => {
+                let mut mapper = RomOnly::new(self.rom);
+                mapper.ram = vec![0x0; ram_size.min(0x2000)];
+                mapper.battery = battery;
+                Box::new(mapper)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Builds an image with correct checksums from the header size codes
+    pub(crate) fn rom_image(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0x0; 0x8000 << rom_size];
+        rom[TITLE_ADDRESS..TITLE_ADDRESS + 4].copy_from_slice(b"TEST");
+        rom[CARTRIDGE_TYPE_ADDRESS] = cartridge_type;
+        rom[ROM_SIZE_ADDRESS] = rom_size;
+        rom[RAM_SIZE_ADDRESS] = ram_size;
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    pub(crate) fn fix_checksums(rom: &mut [u8]) {
+        rom[HEADER_CHECKSUM_ADDRESS] = header_checksum(rom);
+        let [high, low] = global_checksum(rom).to_be_bytes();
+        rom[GLOBAL_CHECKSUM_ADDRESS] = high;
+        rom[GLOBAL_CHECKSUM_ADDRESS + 1] = low;
+    }
+
+    #[test]
+    fn parses_header_fields() {
+        let mut rom = rom_image(0x00, 0x00, 0x00);
+        rom[TITLE_ADDRESS..TITLE_ADDRESS + 16].copy_from_slice(b"POKEMON YELLOW\0\x80");
+        rom[NEW_LICENSEE_ADDRESS..NEW_LICENSEE_ADDRESS + 2].copy_from_slice(b"01");
+        rom[SGB_FLAG_ADDRESS] = 0x03;
+        rom[OLD_LICENSEE_ADDRESS] = 0x33;
+        rom[VERSION_ADDRESS] = 0x01;
+        fix_checksums(&mut rom);
+
+        let header = Cartridge::from_bytes(rom).unwrap().header;
+        assert_eq!(header.title, "POKEMON YELLOW");
+        assert_eq!(header.cgb_support, CGBSupport::Compatible);
+        assert_eq!(header.model(), Model::DMG);
+        assert!(header.sgb_support);
+        assert_eq!(header.cartridge_type.mapper, MapperType::RomOnly);
+        assert_eq!(header.rom_size, 0x8000);
+        assert_eq!(header.ram_size, 0);
+        assert_eq!(header.licensee, Licensee::New("01".to_string()));
+        assert_eq!(header.version, 0x01);
+    }
+
+    #[test]
+    fn cgb_only_games_run_on_the_cgb() {
+        let mut rom = rom_image(0x00, 0x00, 0x00);
+        rom[CGB_FLAG_ADDRESS] = 0xC0;
+        fix_checksums(&mut rom);
+        let header = Cartridge::from_bytes(rom).unwrap().header;
+        assert_eq!(header.cgb_support, CGBSupport::Only);
+        assert_eq!(header.model(), Model::CGB);
+    }
+
+    #[test]
+    fn decodes_cartridge_types() {
+        let cartridge_type = CartridgeType::from_code(0x10).unwrap();
+        assert_eq!(cartridge_type.mapper, MapperType::MBC3);
+        assert_eq!(cartridge_type.to_string(), "MBC3+TIMER+RAM+BATTERY");
+        assert_eq!(CartridgeType::from_code(0x04), None);
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        assert_eq!(
+            Cartridge::from_bytes(vec![0x0; 0x100]).err(),
+            Some(CartridgeError::Truncated {
+                expected: 0x150,
+                actual: 0x100
+            })
+        );
+        let mut rom = rom_image(0x00, 0x01, 0x00);
+        rom.truncate(0x8000);
+        assert_eq!(
+            Cartridge::from_bytes(rom).err(),
+            Some(CartridgeError::Truncated {
+                expected: 0x10000,
+                actual: 0x8000
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut rom = rom_image(0x00, 0x00, 0x00);
+        rom[HEADER_CHECKSUM_ADDRESS] ^= 0xFF;
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::HeaderChecksum { .. })
+        ));
+
+        // the global checksum is only a warning
+        let mut rom = rom_image(0x00, 0x00, 0x00);
+        rom[0x4000] = 0x42;
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert!(matches!(
+            cartridge.warnings[..],
+            [CartridgeWarning::GlobalChecksum { .. }]
+        ));
+        assert!(Cartridge::from_bytes(rom_image(0x00, 0x00, 0x00))
+            .unwrap()
+            .warnings
+            .is_empty());
+    }
+
+    #[test]
+    fn overdumped_images_are_truncated() {
+        let mut rom = rom_image(0x00, 0x00, 0x00);
+        rom.resize(0x10000, 0xFF);
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert_eq!(cartridge.rom.len(), 0x8000);
+        assert_eq!(
+            cartridge.warnings,
+            vec![CartridgeWarning::Overdump {
+                declared: 0x8000,
+                actual: 0x10000
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_hardware() {
+        assert_eq!(
+            Cartridge::from_bytes(rom_image(0xFC, 0x00, 0x00)).err(),
+            Some(CartridgeError::UnsupportedCartridgeType(
+                CartridgeType::from_code(0xFC).unwrap()
+            ))
+        );
+        assert_eq!(
+            Cartridge::from_bytes(rom_image(0x00, 0x00, 0x07)).err(),
+            Some(CartridgeError::UnknownRamSize(0x07))
+        );
+    }
+
+    #[test]
+    fn mapper_comes_from_the_cartridge_type() {
+        let cartridge = Cartridge::from_bytes(rom_image(0x03, 0x02, 0x03)).unwrap();
+        let mut mapper = cartridge.into_mapper();
+        // MBC1 RAM is disabled until 0x0A is written to 0x0000-0x1FFF
+        mapper.write_ram(0xA000, 0x42);
+        assert_eq!(mapper.read_ram(0xA000), 0xFF);
+        mapper.write_rom(0x0000, 0x0A);
+        mapper.write_ram(0xA000, 0x42);
+        assert_eq!(mapper.read_ram(0xA000), 0x42);
+    }
+
+    #[test]
+    fn mbc2_has_battery_backed_ram_without_a_ram_size() {
+        let cartridge = Cartridge::from_bytes(rom_image(0x06, 0x03, 0x00)).unwrap();
+        let mut mapper = cartridge.into_mapper();
+        mapper.write_rom(0x0000, 0x0A);
+        mapper.write_ram(0xA000, 0x05);
+        assert_eq!(mapper.read_ram(0xA000), 0xF5);
+        assert_eq!(mapper.save_data().map(|data| data.len()), Some(0x200));
+    }
+
+    #[test]
+    fn mbc3_timer_is_saved_with_the_ram() {
+        let mut cartridge = Cartridge::from_bytes(rom_image(0x10, 0x06, 0x03)).unwrap();
+        cartridge.rtc_clock = RTCClock::Emulated;
+        let mapper = cartridge.into_mapper();
+        assert_eq!(mapper.save_data().map(|data| data.len()), Some(0x8000 + 48));
+
+        // without TIMER only the RAM is saved
+        let mapper = Cartridge::from_bytes(rom_image(0x13, 0x06, 0x03))
+            .unwrap()
+            .into_mapper();
+        assert_eq!(mapper.save_data().map(|data| data.len()), Some(0x8000));
+    }
+
+    #[test]
+    fn mbc5_rumble_comes_from_the_cartridge_type() {
+        let mut mapper = Cartridge::from_bytes(rom_image(0x1E, 0x02, 0x03))
+            .unwrap()
+            .into_mapper();
+        mapper.write_rom(0x4000, 0x08);
+        assert_eq!(mapper.take_rumble_events().len(), 1);
+
+        let mut mapper = Cartridge::from_bytes(rom_image(0x1B, 0x02, 0x03))
+            .unwrap()
+            .into_mapper();
+        mapper.write_rom(0x4000, 0x08);
+        assert!(mapper.take_rumble_events().is_empty());
+    }
+
+    #[test]
+    fn mbc7_saves_the_eeprom() {
+        let mapper = Cartridge::from_bytes(rom_image(0x22, 0x05, 0x00))
+            .unwrap()
+            .into_mapper();
+        assert_eq!(mapper.save_data().map(|data| data.len()), Some(0x100));
+    }
+
+    #[test]
+    fn cartridge_ram_is_mapped() {
+        let cartridge = Cartridge::from_bytes(rom_image(0x08, 0x00, 0x02)).unwrap();
+        let mut mapper = cartridge.into_mapper();
+        mapper.write_ram(0xA000, 0x42);
+        assert_eq!(mapper.read_ram(0xA000), 0x42);
+        assert_eq!(mapper.read_rom(0x0134), b'T');
+    }
+}
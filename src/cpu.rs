@@ -4,101 +4,332 @@ pub mod instruction;
 pub mod load;
 pub mod register_manipulation;
 pub mod registers;
+pub mod tracer;
 
+use crate::bus::Bus;
+use crate::interrupt::{Interrupt, INTERRUPT_FLAG_ADDRESS};
 use crate::memory_bus::MemoryBus;
+use crate::model::Model;
 
-use instruction::Instruction;
-use instruction::{JumpTest, StackTarget};
+use instruction::{DecodedInstruction, Instruction};
+use instruction::{JumpTest, LoadByteSource, LoadByteTarget, LoadType, StackTarget};
 
 use self::registers::Registers;
+use self::tracer::Tracer;
 
-pub struct CPU {
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StepError {
+    // The SM83 has 11 undefined opcodes: 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB,
+    // 0xEC, 0xED, 0xF4, 0xFC and 0xFD
+    IllegalOpcode { opcode: u8, address: u16 },
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StepError::IllegalOpcode { opcode, address } => write!(
+                f,
+                "illegal opcode 0x{:02x} found at 0x{:04x}",
+                opcode, address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+pub struct CPU<B = MemoryBus> {
     pub registers: Registers,
     pub pc: u16, //program counter
     pub sp: u16, //stack pointer
-    pub bus: MemoryBus,
-    pub interrupts_enabled: bool,
+    pub bus: B,
+    pub interrupts_enabled: bool, //IME
+    pub ime_pending: bool,        //EI sets IME after the following instruction
     pub is_halted: bool,
+    pub halt_bug: bool,                 //next opcode fetch doesn't increment PC
+    pub is_stopped: bool,               //STOP mode, left on a joypad press
+    pub cycle_accurate: bool,           //tick the bus between the memory accesses of an instruction
+    pub lockup_on_illegal_opcode: bool, //hang like hardware instead of returning an error
+    pub is_locked_up: bool,
+    pub test_mode: bool, //LD B,B acts as a software breakpoint (mooneye convention)
+    pub breakpoint_hit: bool, //set when LD B,B is executed in test mode
+    pub tracer: Option<Tracer>, //logs the state before every instruction
+    ticked_cycles: u8,   //cycles of the current step already given to the bus
+    pub cycles: u64,     //normal speed T-cycles executed since power on
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
     pub fn new() -> Self {
+        Self::with_bus(MemoryBus::new())
+    }
+
+    /// Creates a CPU in the state the boot ROM of `model` leaves it in
+    /// when it jumps to the cartridge entry point at 0x0100.
+    pub fn with_model(model: Model) -> Self {
+        let mut cpu = Self::new();
+        cpu.reset_to_post_boot(model);
+        cpu
+    }
+
+    /// Sets the registers and IO registers to their values right after the
+    /// boot ROM of `model` has run. The DMG and MGB boot ROMs leave the
+    /// half carry and carry flags set unless the header checksum at 0x014D is
+    /// 0, so the cartridge should already be on the bus.
+    pub fn reset_to_post_boot(&mut self, model: Model) {
+        let header_checksum = self.bus.read_byte(0x014D);
+        let checksum_flags = if header_checksum != 0 { 0x30 } else { 0x00 };
+
+        // A, F, B, C, D, E, H, L
+        let registers: [u8; 8] = match model {
+            Model::DMG0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+            Model::DMG | Model::MGB => {
+                let a = if model == Model::DMG { 0x01 } else { 0xFF };
+                [a, 0x80 | checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D]
+            }
+            Model::SGB => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::SGB2 => [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::CGB => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::AGB => [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        };
+        let [a, f, b, c, d, e, h, l] = registers;
+        self.registers.a = a;
+        self.registers.f = f.into();
+        self.registers.b = b;
+        self.registers.c = c;
+        self.registers.d = d;
+        self.registers.e = e;
+        self.registers.h = h;
+        self.registers.l = l;
+
+        self.pc = 0x0100;
+        self.sp = 0xFFFE;
+        self.interrupts_enabled = false;
+        self.ime_pending = false;
+        self.is_halted = false;
+        self.halt_bug = false;
+        self.is_stopped = false;
+        self.is_locked_up = false;
+
+        self.bus.reset_to_post_boot(model);
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    pub fn with_bus(bus: B) -> Self {
         Self {
             registers: Registers::new(),
             pc: 0x0,
-            sp: 0x0, //FIXME: change begin of stack pointer
-            bus: MemoryBus::new(),
+            sp: 0x0, //set by the boot ROM, see `CPU::with_model`
+            bus,
             interrupts_enabled: true,
+            ime_pending: false,
             is_halted: false,
+            halt_bug: false,
+            is_stopped: false,
+            cycle_accurate: false,
+            lockup_on_illegal_opcode: false,
+            is_locked_up: false,
+            test_mode: false,
+            breakpoint_hit: false,
+            tracer: None,
+            ticked_cycles: 0,
+            cycles: 0,
         }
     }
 
-    fn step(&mut self) {
-        let mut instruction_byte = self.bus.read_byte(self.pc);
+    /// Services the highest priority pending interrupt if IME is set,
+    /// otherwise fetches, decodes and executes the instruction at PC.
+    /// A halted CPU idles for 4 T-cycles instead until an interrupt is pending.
+    /// Returns the number of T-cycles it took.
+    ///
+    /// An undefined opcode is reported as a `StepError` and leaves PC on it,
+    /// unless `lockup_on_illegal_opcode` is set. In that case the CPU locks
+    /// up like a real SM83: it stops executing and ignores interrupts, while
+    /// the rest of the system keeps running.
+    ///
+    /// In CGB double speed mode the rest of the system only sees half of
+    /// those cycles, so `cycles` advances at half the rate.
+    ///
+    /// When `cycle_accurate` is set, the bus is ticked one M-cycle at a time
+    /// before each memory access, so peripherals observe every read and write
+    /// in the M-cycle where it happens on hardware. Otherwise the whole
+    /// instruction runs first and the bus catches up afterwards.
+    pub fn step(&mut self) -> Result<u8, StepError> {
+        if self.is_stopped {
+            return Ok(self.stopped_step());
+        }
+
+        self.ticked_cycles = 0;
+        let cycles = if self.is_locked_up {
+            4
+        } else if let Some(interrupt) = self.interrupt_to_service() {
+            self.service_interrupt(interrupt)
+        } else if self.is_halted {
+            self.halted_step()?
+        } else {
+            self.execute_next_instruction()?
+        };
+        // cycles without a memory access at the end of the instruction
+        self.bus.tick(cycles.saturating_sub(self.ticked_cycles));
+        self.cycles += if self.bus.double_speed() {
+            cycles / 2
+        } else {
+            cycles
+        } as u64;
+        Ok(cycles)
+    }
+
+    // The system clock is stopped, so nothing on the bus advances until a
+    // joypad press, signaled by peripherals through the joypad interrupt.
+    fn stopped_step(&mut self) -> u8 {
+        if self.bus.peek(INTERRUPT_FLAG_ADDRESS) & Interrupt::Joypad.mask() != 0 {
+            self.is_stopped = false;
+        }
+        self.cycles += 4;
+        4
+    }
+
+    // The CPU leaves HALT as soon as IE & IF != 0, even when IME is not set.
+    // In that case the interrupt is not serviced and execution simply
+    // continues after the HALT instruction.
+    fn halted_step(&mut self) -> Result<u8, StepError> {
+        if self.bus.pending_interrupt().is_some() {
+            self.is_halted = false;
+            self.execute_next_instruction()
+        } else {
+            Ok(4)
+        }
+    }
+
+    fn interrupt_to_service(&self) -> Option<Interrupt> {
+        if self.interrupts_enabled {
+            self.bus.pending_interrupt()
+        } else {
+            None
+        }
+    }
+
+    // DESCRIPTION: disable IME, clear the IF bit of the interrupt, push PC
+    // and jump to the interrupt vector
+    // Cycles: 20 (2 idle, 2 writes, 1 idle)
+    fn service_interrupt(&mut self, interrupt: Interrupt) -> u8 {
+        self.internal_cycle();
+        self.interrupts_enabled = false;
+        self.is_halted = false;
+        if self.halt_bug {
+            // EI; HALT with an interrupt pending: PC was never incremented
+            // past HALT, so the handler returns to the HALT instruction
+            self.halt_bug = false;
+            self.pc = self.pc.wrapping_sub(1);
+        }
+        self.bus.acknowledge_interrupt(interrupt);
+        self.push(self.pc);
+        self.pc = interrupt.vector();
+        20
+    }
+
+    fn execute_next_instruction(&mut self) -> Result<u8, StepError> {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self);
+            self.tracer = Some(tracer);
+        }
+        let enable_interrupts = self.ime_pending;
+        let address = self.pc;
+        let mut instruction_byte = self.read_byte(address);
+        if self.halt_bug {
+            // PC fails to increment after this fetch, so the opcode byte is
+            // read a second time as the next byte of the instruction
+            self.halt_bug = false;
+            self.pc = self.pc.wrapping_sub(1);
+        }
         let prefixed = instruction_byte == 0xCB;
         if prefixed {
-            instruction_byte = self.bus.read_byte(self.pc + 1);
+            instruction_byte = self.read_byte(self.pc.wrapping_add(1));
         }
 
         let (next_pc, cycles) =
-            if let Some(instruction) = Instruction::from_byte(instruction_byte, prefixed) {
-                self.execute(instruction)
+            if let Some(decoded) = Instruction::decode(instruction_byte, prefixed) {
+                self.execute_decoded(decoded)
             } else {
-                let description = format!(
-                    "0x{}{:x}",
-                    if prefixed { "cb" } else { "" },
-                    instruction_byte
-                );
-                panic!("Unkown instruction found for: {}", description)
+                // the HALT bug doesn't apply to an opcode that never executes
+                self.pc = address;
+                if self.lockup_on_illegal_opcode {
+                    self.is_locked_up = true;
+                    return Ok(4);
+                }
+                // every 0xCB prefixed opcode is defined
+                return Err(StepError::IllegalOpcode {
+                    opcode: instruction_byte,
+                    address,
+                });
             };
 
         self.pc = next_pc;
+        // IME is set once the instruction following EI is done, unless that
+        // instruction was DI
+        if enable_interrupts && self.ime_pending {
+            self.ime_pending = false;
+            self.interrupts_enabled = true;
+        }
+        Ok(cycles)
     }
 
     pub fn execute(&mut self, instruction: Instruction) -> (u16, u8) {
-        match instruction {
-            Instruction::ADD(_) => alu::execute(self, instruction),
-            Instruction::ADC(_) => alu::execute(self, instruction),
-            Instruction::SUB(_) => alu::execute(self, instruction),
-            Instruction::SBC(_) => alu::execute(self, instruction),
-            Instruction::AND(_) => alu::execute(self, instruction),
-            Instruction::XOR(_) => alu::execute(self, instruction),
-            Instruction::OR(_) => alu::execute(self, instruction),
-            Instruction::CP(_) => alu::execute(self, instruction),
-            Instruction::INC(_) => alu::execute(self, instruction),
-            Instruction::DEC(_) => alu::execute(self, instruction),
-            Instruction::DAA => alu::execute(self, instruction),
-            Instruction::CPL => alu::execute(self, instruction),
-
-            Instruction::ADDHL(_) => alu::execute(self, instruction),
-            Instruction::ADDSP => alu::execute(self, instruction),
-
-            Instruction::CCF => alu::execute(self, instruction),
-            Instruction::SCF => alu::execute(self, instruction),
-
-            Instruction::LD(load_type) => load::execute(self, load_type),
+        self.execute_decoded(&DecodedInstruction::new(instruction))
+    }
+
+    fn execute_decoded(&mut self, decoded: &DecodedInstruction) -> (u16, u8) {
+        let instruction = decoded.instruction;
+        // control flow instructions return the address they jump to, every
+        // other instruction continues right after its encoded bytes
+        let jump_target = match instruction {
+            Instruction::ADD(_)
+            | Instruction::ADC(_)
+            | Instruction::SUB(_)
+            | Instruction::SBC(_)
+            | Instruction::AND(_)
+            | Instruction::XOR(_)
+            | Instruction::OR(_)
+            | Instruction::CP(_)
+            | Instruction::INC(_)
+            | Instruction::DEC(_)
+            | Instruction::DAA
+            | Instruction::CPL
+            | Instruction::ADDHL(_)
+            | Instruction::ADDSP
+            | Instruction::CCF
+            | Instruction::SCF => {
+                alu::execute(self, instruction);
+                None
+            }
+
+            Instruction::LD(load_type) => {
+                if self.test_mode
+                    && load_type == LoadType::Byte(LoadByteTarget::B, LoadByteSource::B)
+                {
+                    self.breakpoint_hit = true;
+                }
+                load::execute(self, load_type);
+                None
+            }
 
             Instruction::JP(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    JumpTest::NotCarry => !self.registers.f.carry,
-                    JumpTest::Zero => self.registers.f.zero,
-                    JumpTest::Carry => self.registers.f.carry,
-                    JumpTest::Always => true,
-                };
+                let jump_condition = self.test_jump_condition(test);
                 self.jump(jump_condition)
             }
             Instruction::JR(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    JumpTest::NotCarry => !self.registers.f.carry,
-                    JumpTest::Zero => self.registers.f.zero,
-                    JumpTest::Carry => self.registers.f.carry,
-                    JumpTest::Always => true,
-                };
+                let jump_condition = self.test_jump_condition(test);
                 self.jump_relative(jump_condition)
             }
-            Instruction::JPI => (self.registers.get_hl(), 4),
+            Instruction::JPI => Some(self.registers.get_hl()),
 
             Instruction::PUSH(target) => {
                 let value = match target {
@@ -108,7 +339,7 @@ impl CPU {
                     StackTarget::HL => self.registers.get_hl(),
                 };
                 self.push(value);
-                (self.pc.wrapping_add(1), 16)
+                None
             }
             Instruction::POP(target) => {
                 let result = self.pop();
@@ -118,139 +349,157 @@ impl CPU {
                     StackTarget::DE => self.registers.set_de(result),
                     StackTarget::HL => self.registers.set_hl(result),
                 };
-                (self.pc.wrapping_add(1), 12)
+                None
             }
             Instruction::CALL(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    JumpTest::NotCarry => !self.registers.f.carry,
-                    JumpTest::Zero => self.registers.f.zero,
-                    JumpTest::Carry => self.registers.f.carry,
-                    JumpTest::Always => true,
-                };
+                let jump_condition = self.test_jump_condition(test);
                 self.call(jump_condition)
             }
             Instruction::RET(test) => {
-                let jump_condition = match test {
-                    JumpTest::NotZero => !self.registers.f.zero,
-                    JumpTest::NotCarry => !self.registers.f.carry,
-                    JumpTest::Zero => self.registers.f.zero,
-                    JumpTest::Carry => self.registers.f.carry,
-                    JumpTest::Always => true,
-                };
-                let next_pc = self.ret(jump_condition);
-
-                let cycles = if jump_condition && test == JumpTest::Always {
-                    16
-                } else if jump_condition {
-                    20
-                } else {
-                    8
-                };
-                (next_pc, cycles)
+                if test != JumpTest::Always {
+                    // evaluating the condition takes an extra M-cycle
+                    self.internal_cycle();
+                }
+                let jump_condition = self.test_jump_condition(test);
+                self.ret(jump_condition)
             }
 
-            Instruction::RRA => register_manipulation::execute(self, instruction),
-            Instruction::RLA => register_manipulation::execute(self, instruction),
-            Instruction::RRCA => register_manipulation::execute(self, instruction),
-            Instruction::RLCA => register_manipulation::execute(self, instruction),
-            Instruction::BIT(_, _) => register_manipulation::execute(self, instruction),
-            Instruction::RES(_, _) => register_manipulation::execute(self, instruction),
-            Instruction::SET(_, _) => register_manipulation::execute(self, instruction),
-            Instruction::SRL(_) => register_manipulation::execute(self, instruction),
-            Instruction::RR(_) => register_manipulation::execute(self, instruction),
-            Instruction::RL(_) => register_manipulation::execute(self, instruction),
-            Instruction::RRC(_) => register_manipulation::execute(self, instruction),
-            Instruction::RLC(_) => register_manipulation::execute(self, instruction),
-            Instruction::SRA(_) => register_manipulation::execute(self, instruction),
-            Instruction::SLA(_) => register_manipulation::execute(self, instruction),
-            Instruction::SWAP(_) => register_manipulation::execute(self, instruction),
+            Instruction::RRA
+            | Instruction::RLA
+            | Instruction::RRCA
+            | Instruction::RLCA
+            | Instruction::BIT(_, _)
+            | Instruction::RES(_, _)
+            | Instruction::SET(_, _)
+            | Instruction::SRL(_)
+            | Instruction::RR(_)
+            | Instruction::RL(_)
+            | Instruction::RRC(_)
+            | Instruction::RLC(_)
+            | Instruction::SRA(_)
+            | Instruction::SLA(_)
+            | Instruction::SWAP(_) => {
+                register_manipulation::execute(self, instruction);
+                None
+            }
 
             Instruction::RETI => {
+                // unlike EI, RETI enables interrupts immediately
                 self.interrupts_enabled = true;
-                (self.pop(), 16)
+                Some(self.pop())
             }
             Instruction::RST(location) => {
                 self.rst();
-                (location.to_hex(), 24)
+                Some(location.to_hex())
             }
 
             Instruction::HALT => {
-                self.is_halted = true;
-                (self.pc.wrapping_add(1), 4)
-            },
-            Instruction::NOP => (1, 4),
+                // HALT bug: with IME unset and an interrupt already pending the
+                // CPU doesn't halt, but the next opcode fetch repeats a byte
+                if !self.interrupts_enabled && self.bus.pending_interrupt().is_some() {
+                    self.halt_bug = true;
+                } else {
+                    self.is_halted = true;
+                }
+                None
+            }
+            Instruction::STOP => {
+                // on CGB, STOP performs the speed switch prepared through
+                // KEY1 instead of entering low power mode
+                if !self.bus.stop() {
+                    self.is_stopped = true;
+                }
+                None
+            }
+            Instruction::NOP => None,
             Instruction::DI => {
                 self.interrupts_enabled = false;
-                (self.pc.wrapping_add(1), 4)
+                self.ime_pending = false;
+                None
             }
             Instruction::EI => {
-                self.interrupts_enabled = true;
-                (self.pc.wrapping_add(1), 4)
+                self.ime_pending = true;
+                None
             }
-            // _ => { /*add support for more instructions*/ }
+        };
+
+        match jump_target {
+            Some(address) => (address, decoded.branch_cycles),
+            None => (self.pc.wrapping_add(decoded.size), decoded.cycles),
         }
     }
 
-    fn jump(&self, jump_condition: bool) -> (u16, u8) {
+    fn test_jump_condition(&self, test: JumpTest) -> bool {
+        match test {
+            JumpTest::NotZero => !self.registers.f.zero,
+            JumpTest::NotCarry => !self.registers.f.carry,
+            JumpTest::Zero => self.registers.f.zero,
+            JumpTest::Carry => self.registers.f.carry,
+            JumpTest::Always => true,
+        }
+    }
+
+    fn jump(&mut self, jump_condition: bool) -> Option<u16> {
+        // the address is read even if we don't jump
+        let address = self.read_next_word();
         if jump_condition {
-            (self.read_next_word(), 16)
+            Some(address)
         } else {
-            // If we don't jump we need to still move the program
-            // counter forward by 3 since the jump instruction is
-            // 3 bytes wide (1 byte for tag and 2 bytes for jump address)
-            (self.pc.wrapping_add(3), 12)
+            None
         }
     }
 
-    fn jump_relative(&self, should_jump: bool) -> (u16, u8) {
+    fn jump_relative(&mut self, should_jump: bool) -> Option<u16> {
         let next_step = self.pc.wrapping_add(2);
+        let offset = self.read_next_byte() as i8;
         if should_jump {
-            let offset = self.read_next_byte() as i8;
             let pc = if offset >= 0 {
                 next_step.wrapping_add(offset as u16)
             } else {
-                next_step.wrapping_sub(offset.abs() as u16)
+                next_step.wrapping_sub(offset.unsigned_abs() as u16)
             };
-            (pc, 16)
+            Some(pc)
         } else {
-            (next_step, 12)
+            None
         }
     }
 
     fn push(&mut self, value: u16) {
+        // SP is decremented during an idle M-cycle before the first write
+        self.internal_cycle();
         self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, ((value & 0xFF00) >> 8) as u8);
+        self.write_byte(self.sp, ((value & 0xFF00) >> 8) as u8);
 
         self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, (value & 0xFF) as u8);
+        self.write_byte(self.sp, (value & 0xFF) as u8);
     }
 
     fn pop(&mut self) -> u16 {
-        let lsb = self.bus.read_byte(self.sp) as u16;
+        let lsb = self.read_byte(self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
 
-        let msb = self.bus.read_byte(self.sp) as u16;
+        let msb = self.read_byte(self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
 
         (msb << 8) | lsb
     }
 
-    fn call(&mut self, condition: bool) -> (u16, u8) {
+    fn call(&mut self, condition: bool) -> Option<u16> {
         let next_pc = self.pc.wrapping_add(3);
+        let address = self.read_next_word();
         if condition {
             self.push(next_pc);
-            (self.read_next_word(), 24)
+            Some(address)
         } else {
-            (next_pc, 12)
+            None
         }
     }
 
-    fn ret(&mut self, condition: bool) -> u16 {
+    fn ret(&mut self, condition: bool) -> Option<u16> {
         if condition {
-            self.pop()
+            Some(self.pop())
         } else {
-            self.pc.wrapping_add(1)
+            None
         }
     }
 
@@ -258,15 +507,544 @@ impl CPU {
         self.push(self.pc.wrapping_add(1));
     }
 
-    pub fn read_next_byte(&self) -> u8 {
-        self.bus.read_byte(self.pc + 1) as u8
+    pub fn read_next_byte(&mut self) -> u8 {
+        self.read_byte(self.pc.wrapping_add(1))
     }
 
-    pub fn read_next_word(&self) -> u16 {
+    pub fn read_next_word(&mut self) -> u16 {
         // Gameboy is little endian so read pc + 2 as most significant bit
         // and pc + 1 as least significant bi
-        let least_significant_byte = self.bus.read_byte(self.pc + 1) as u16;
-        let most_significant_byte = self.bus.read_byte(self.pc + 2) as u16;
+        let least_significant_byte = self.read_byte(self.pc.wrapping_add(1)) as u16;
+        let most_significant_byte = self.read_byte(self.pc.wrapping_add(2)) as u16;
         (most_significant_byte << 8) | least_significant_byte
     }
+
+    /// Reads a byte from the bus, taking one M-cycle
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick_m_cycle();
+        self.bus.read(address)
+    }
+
+    /// Writes a byte to the bus, taking one M-cycle
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.tick_m_cycle();
+        self.bus.write(address, value);
+    }
+
+    // An M-cycle where the CPU doesn't access the bus. Only needed when it
+    // happens before a memory access of the same instruction, trailing ones
+    // are ticked at the end of the step.
+    fn internal_cycle(&mut self) {
+        self.tick_m_cycle();
+        self.bus.idle();
+    }
+
+    fn tick_m_cycle(&mut self) {
+        if self.cycle_accurate {
+            self.bus.tick(4);
+            self.ticked_cycles = self.ticked_cycles.saturating_add(4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{BusAccess, RecordingBus};
+    #[test]
+    fn services_highest_priority_interrupt() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x1234;
+        cpu.sp = 0xFFFE;
+        cpu.bus.interrupt_enable = Interrupt::Timer.mask() | Interrupt::Joypad.mask();
+        cpu.bus.request_interrupt(Interrupt::Joypad);
+        cpu.bus.request_interrupt(Interrupt::Timer);
+
+        assert_eq!(cpu.step(), Ok(20));
+        assert_eq!(cpu.pc, 0x50);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(cpu.bus.read_byte(0xFFFD), 0x12);
+        assert_eq!(cpu.bus.read_byte(0xFFFC), 0x34);
+        assert!(!cpu.interrupts_enabled);
+        assert_eq!(cpu.bus.interrupt_flag, Interrupt::Joypad.mask());
+    }
+
+    #[test]
+    fn interrupts_are_not_serviced_without_ime() {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.bus.interrupt_enable = Interrupt::VBlank.mask();
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.bus.interrupt_flag, Interrupt::VBlank.mask());
+    }
+
+    #[test]
+    fn servicing_an_interrupt_wakes_the_cpu() {
+        let mut cpu = CPU::new();
+        cpu.sp = 0xFFFE;
+        cpu.is_halted = true;
+        cpu.bus.interrupt_enable = Interrupt::Serial.mask();
+        cpu.bus.request_interrupt(Interrupt::Serial);
+
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.pc, 0x58);
+    }
+
+    #[test]
+    fn halted_cpu_idles_until_an_interrupt_is_pending() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x76] = 0x76; // HALT
+        cpu.pc = 0x76;
+        cpu.step().unwrap();
+        assert!(cpu.is_halted);
+        assert_eq!(cpu.pc, 0x77);
+
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.pc, 0x77);
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[test]
+    fn halt_wakes_up_without_ime() {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x76; // HALT
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x3C; // INC A
+        cpu.step().unwrap();
+        assert!(cpu.is_halted);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x1);
+
+        cpu.bus.interrupt_enable = Interrupt::Timer.mask();
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.pc, 0x2);
+        // the interrupt is left pending since IME is not set
+        assert_eq!(cpu.bus.interrupt_flag, Interrupt::Timer.mask());
+    }
+
+    #[test]
+    fn halt_bug_reads_next_byte_twice() {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.bus.interrupt_enable = Interrupt::VBlank.mask();
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x76; // HALT
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x3C; // INC A
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x04; // INC B
+
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.pc, 0x1);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x1);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x2);
+        assert_eq!(cpu.registers.a, 2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 1);
+    }
+
+    #[test]
+    fn halt_bug_repeats_opcode_as_operand() {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.bus.interrupt_enable = Interrupt::VBlank.mask();
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x76; // HALT
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x3E; // LD A, d8
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x14;
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        // LD A, 0x3E is executed, then 0x14 (INC D) is decoded
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.pc, 0x2);
+    }
+
+    #[test]
+    fn stop_resets_div_and_waits_for_joypad() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x10; // STOP
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x3C; // INC A
+        cpu.bus.div_counter = 0xABCC;
+
+        cpu.step().unwrap();
+        assert!(cpu.is_stopped);
+        assert_eq!(cpu.pc, 0x2);
+        assert_eq!(cpu.bus.read_byte(0xFF04), 0);
+
+        let div_counter = cpu.bus.div_counter;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x2);
+        assert_eq!(cpu.bus.div_counter, div_counter);
+
+        cpu.bus.request_interrupt(Interrupt::Joypad);
+        cpu.step().unwrap();
+        assert!(!cpu.is_stopped);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.pc, 0x3);
+    }
+
+    #[test]
+    fn stop_switches_speed_when_armed() {
+        let mut cpu = CPU::new();
+        cpu.bus.cgb_mode = true;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x10; // STOP
+        cpu.bus.write_byte(0xFF4D, 0x1);
+
+        cpu.step().unwrap();
+        assert!(!cpu.is_stopped);
+        assert!(cpu.bus.double_speed);
+        assert_eq!(cpu.bus.read_byte(0xFF4D), 0xFE);
+
+        // a NOP now only takes 2 cycles of the rest of the system
+        let cycles = cpu.cycles;
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.cycles - cycles, 2);
+    }
+
+    #[test]
+    fn post_boot_registers_identify_the_model() {
+        assert_eq!(CPU::with_model(Model::DMG).registers.a, 0x01);
+        assert_eq!(CPU::with_model(Model::MGB).registers.a, 0xFF);
+        assert_eq!(CPU::with_model(Model::SGB2).registers.a, 0xFF);
+        assert_eq!(CPU::with_model(Model::CGB).registers.a, 0x11);
+        // the AGB boot ROM runs an extra INC B
+        assert_eq!(CPU::with_model(Model::CGB).registers.b, 0x00);
+        assert_eq!(CPU::with_model(Model::AGB).registers.b, 0x01);
+    }
+
+    #[test]
+    fn post_boot_dmg_state() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x014D] = 0xE7;
+        cpu.reset_to_post_boot(Model::DMG);
+
+        assert_eq!(cpu.registers.get_af(), 0x01B0);
+        assert_eq!(cpu.registers.get_bc(), 0x0013);
+        assert_eq!(cpu.registers.get_de(), 0x00D8);
+        assert_eq!(cpu.registers.get_hl(), 0x014D);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.pc, 0x0100);
+        assert!(!cpu.interrupts_enabled);
+    }
+
+    #[test]
+    fn post_boot_flags_depend_on_header_checksum() {
+        let cpu = CPU::with_model(Model::DMG);
+        assert_eq!(u8::from(cpu.registers.f), 0x80);
+    }
+
+    #[test]
+    fn post_boot_cgb_state() {
+        let cpu = CPU::with_model(Model::CGB);
+        assert_eq!(cpu.registers.get_af(), 0x1180);
+        assert_eq!(cpu.registers.get_bc(), 0x0000);
+        assert_eq!(cpu.registers.get_de(), 0xFF56);
+        assert_eq!(cpu.registers.get_hl(), 0x000D);
+        assert!(cpu.bus.cgb_mode);
+    }
+
+    fn cpu_with_pending_vblank() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.sp = 0xFFFE;
+        cpu.bus.interrupt_enable = Interrupt::VBlank.mask();
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+        cpu
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_the_next_instruction() {
+        let mut cpu = cpu_with_pending_vblank();
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xFB; // EI
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x3C; // INC A
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x04; // INC B
+
+        cpu.step().unwrap();
+        assert!(!cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 1);
+        assert!(cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(cpu.registers.b, 0);
+        assert_eq!(cpu.pop(), 0x2);
+    }
+
+    #[test]
+    fn di_cancels_a_pending_ei() {
+        let mut cpu = cpu_with_pending_vblank();
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xFB; // EI
+        cpu.bus.cartridge.rom_mut()[0x1] = 0xF3; // DI
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x3C; // INC A
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(!cpu.interrupts_enabled);
+        assert!(!cpu.ime_pending);
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.pc, 0x3);
+    }
+
+    #[test]
+    fn ei_reti_returns_before_servicing_interrupt() {
+        let mut cpu = cpu_with_pending_vblank();
+        cpu.pc = 0x100;
+        cpu.push(0x200);
+        cpu.bus.cartridge.rom_mut()[0x100] = 0xFB; // EI
+        cpu.bus.cartridge.rom_mut()[0x101] = 0xD9; // RETI
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x200);
+        assert!(cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(cpu.pop(), 0x200);
+    }
+
+    #[test]
+    fn reti_enables_interrupts_immediately() {
+        let mut cpu = cpu_with_pending_vblank();
+        cpu.pc = 0x100;
+        cpu.push(0x200);
+        cpu.bus.cartridge.rom_mut()[0x100] = 0xD9; // RETI
+
+        cpu.step().unwrap();
+        assert!(cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+    }
+
+    #[test]
+    fn ei_halt_with_pending_interrupt_returns_to_halt() {
+        let mut cpu = cpu_with_pending_vblank();
+        cpu.pc = 0x100;
+        cpu.bus.cartridge.rom_mut()[0x100] = 0xFB; // EI
+        cpu.bus.cartridge.rom_mut()[0x101] = 0x76; // HALT
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(cpu.pop(), 0x101);
+    }
+
+    #[test]
+    fn ei_halt_waits_for_interrupt() {
+        let mut cpu = CPU::new();
+        cpu.interrupts_enabled = false;
+        cpu.sp = 0xFFFE;
+        cpu.bus.interrupt_enable = Interrupt::Timer.mask();
+        cpu.pc = 0x100;
+        cpu.bus.cartridge.rom_mut()[0x100] = 0xFB; // EI
+        cpu.bus.cartridge.rom_mut()[0x101] = 0x76; // HALT
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.is_halted);
+        assert!(cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert!(cpu.is_halted);
+
+        cpu.bus.request_interrupt(Interrupt::Timer);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.pc, 0x50);
+        assert_eq!(cpu.pop(), 0x102);
+    }
+
+    #[test]
+    fn cycle_accurate_read_happens_in_last_m_cycle() {
+        let mut cpu = CPU::new();
+        cpu.cycle_accurate = true;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xF0; // LDH A, (0x04)
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x04;
+        cpu.bus.div_counter = 0x00F4;
+
+        assert_eq!(cpu.step(), Ok(12));
+        // DIV is read after 12 cycles: 0x00F4 + 12 = 0x0100
+        assert_eq!(cpu.registers.a, 0x01);
+        assert_eq!(cpu.bus.div_counter, 0x0100);
+    }
+
+    #[test]
+    fn instant_read_happens_before_bus_ticks() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xF0; // LDH A, (0x04)
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x04;
+        cpu.bus.div_counter = 0x00F4;
+
+        assert_eq!(cpu.step(), Ok(12));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(cpu.bus.div_counter, 0x0100);
+    }
+
+    // Writing to DIV resets it, so the value left in the counter tells how
+    // many cycles were ticked after the last write of the instruction
+    #[test]
+    fn cycle_accurate_sp_store_writes_in_m_cycles_4_and_5() {
+        let mut cpu = CPU::new();
+        cpu.cycle_accurate = true;
+        cpu.sp = 0xABCD;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x08; // LD (0xFF03), SP
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x03;
+        cpu.bus.cartridge.rom_mut()[0x2] = 0xFF;
+        cpu.bus.div_counter = 0x1234;
+
+        assert_eq!(cpu.step(), Ok(20));
+        assert_eq!(cpu.bus.read_byte(0xFF03), 0xCD);
+        assert_eq!(cpu.bus.div_counter, 0);
+    }
+
+    #[test]
+    fn cycle_accurate_push_writes_after_internal_cycle() {
+        let mut cpu = CPU::new();
+        cpu.cycle_accurate = true;
+        cpu.sp = 0xFF06;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xC5; // PUSH BC
+        cpu.bus.div_counter = 0x1234;
+
+        assert_eq!(cpu.step(), Ok(16));
+        assert_eq!(cpu.bus.div_counter, 0);
+    }
+
+    #[test]
+    fn cycle_accurate_call_pushes_in_last_m_cycles() {
+        let mut cpu = CPU::new();
+        cpu.cycle_accurate = true;
+        cpu.sp = 0xFF05;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xCD; // CALL 0x1234
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x34;
+        cpu.bus.cartridge.rom_mut()[0x2] = 0x12;
+        cpu.bus.div_counter = 0x1234;
+
+        assert_eq!(cpu.step(), Ok(24));
+        // the high byte of the return address is written to DIV in M-cycle 5
+        assert_eq!(cpu.bus.div_counter, 4);
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn instant_mode_ticks_whole_instruction_after_writes() {
+        let mut cpu = CPU::new();
+        cpu.sp = 0xFF06;
+        cpu.bus.cartridge.rom_mut()[0x0] = 0xC5; // PUSH BC
+        cpu.bus.div_counter = 0x1234;
+
+        assert_eq!(cpu.step(), Ok(16));
+        assert_eq!(cpu.bus.div_counter, 16);
+    }
+
+    #[test]
+    fn illegal_opcode_returns_an_error() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x150;
+        cpu.bus.cartridge.rom_mut()[0x150] = 0xD3;
+
+        assert_eq!(
+            cpu.step(),
+            Err(StepError::IllegalOpcode {
+                opcode: 0xD3,
+                address: 0x150
+            })
+        );
+        assert_eq!(cpu.pc, 0x150);
+        assert!(!cpu.is_locked_up);
+    }
+
+    #[test]
+    fn illegal_opcode_after_halt_bug_reports_its_address() {
+        let mut cpu = CPU::new();
+        cpu.pc = 0x150;
+        cpu.halt_bug = true;
+        cpu.bus.cartridge.rom_mut()[0x150] = 0xD3;
+
+        assert_eq!(
+            cpu.step(),
+            Err(StepError::IllegalOpcode {
+                opcode: 0xD3,
+                address: 0x150
+            })
+        );
+        assert_eq!(cpu.pc, 0x150);
+
+        cpu.lockup_on_illegal_opcode = true;
+        cpu.halt_bug = true;
+        assert_eq!(cpu.step(), Ok(4));
+        assert!(cpu.is_locked_up);
+        assert_eq!(cpu.pc, 0x150);
+    }
+
+    #[test]
+    fn illegal_opcode_locks_up_in_hardware_mode() {
+        let mut cpu = CPU::new();
+        cpu.lockup_on_illegal_opcode = true;
+        cpu.sp = 0xFFFE;
+        cpu.pc = 0x150;
+        cpu.bus.cartridge.rom_mut()[0x150] = 0xFD;
+
+        assert_eq!(cpu.step(), Ok(4));
+        assert!(cpu.is_locked_up);
+
+        cpu.bus.interrupt_enable = Interrupt::VBlank.mask();
+        cpu.bus.request_interrupt(Interrupt::VBlank);
+        assert_eq!(cpu.step(), Ok(4));
+        assert_eq!(cpu.pc, 0x150);
+        assert_eq!(cpu.bus.div_counter, 8);
+    }
+
+    #[test]
+    fn bus_accesses_are_recorded() {
+        let mut cpu = CPU::with_bus(RecordingBus::new(MemoryBus::new()));
+        cpu.sp = 0xD000;
+        cpu.registers.set_bc(0x1234);
+        cpu.bus.inner.cartridge.rom_mut()[0x0] = 0xC5; // PUSH BC
+
+        assert_eq!(cpu.step(), Ok(16));
+        assert_eq!(
+            cpu.bus.accesses,
+            vec![
+                BusAccess::Read {
+                    address: 0x0,
+                    value: 0xC5
+                },
+                BusAccess::Idle,
+                BusAccess::Write {
+                    address: 0xCFFF,
+                    value: 0x12
+                },
+                BusAccess::Write {
+                    address: 0xCFFE,
+                    value: 0x34
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ld_b_b_is_a_breakpoint_in_test_mode() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x0] = 0x40; // LD B,B
+        cpu.bus.cartridge.rom_mut()[0x1] = 0x40;
+        cpu.step().unwrap();
+        assert!(!cpu.breakpoint_hit);
+
+        cpu.test_mode = true;
+        cpu.step().unwrap();
+        assert!(cpu.breakpoint_hit);
+        assert_eq!(cpu.pc, 0x2);
+    }
 }
@@ -7,117 +7,86 @@
 
 use super::instruction::Instruction;
 use super::instruction::{ADDHLTarget, ArithmeticTarget, IncDecTarget};
+use crate::bus::Bus;
 use crate::cpu::CPU;
 
-pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
+pub fn execute<B: Bus>(cpu: &mut CPU<B>, instruction: Instruction) {
     match instruction {
-        Instruction::ADD(target) => {
-            match target {
-                ArithmeticTarget::A => cpu.registers.a = add(cpu, cpu.registers.a, false),
-                ArithmeticTarget::B => cpu.registers.a = add(cpu, cpu.registers.b, false),
-                ArithmeticTarget::C => cpu.registers.a = add(cpu, cpu.registers.c, false),
-                ArithmeticTarget::D => cpu.registers.a = add(cpu, cpu.registers.d, false),
-                ArithmeticTarget::E => cpu.registers.a = add(cpu, cpu.registers.e, false),
-                ArithmeticTarget::H => cpu.registers.a = add(cpu, cpu.registers.h, false),
-                ArithmeticTarget::L => cpu.registers.a = add(cpu, cpu.registers.l, false),
-                ArithmeticTarget::HLI => {
-                    let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
-                    cpu.registers.a = add(cpu, value, false);
-                }
-                ArithmeticTarget::D8 => {
-                    let value = cpu.read_next_byte();
-                    cpu.registers.a = add(cpu, value, false);
-                }
+        Instruction::ADD(target) => match target {
+            ArithmeticTarget::A => cpu.registers.a = add(cpu, cpu.registers.a, false),
+            ArithmeticTarget::B => cpu.registers.a = add(cpu, cpu.registers.b, false),
+            ArithmeticTarget::C => cpu.registers.a = add(cpu, cpu.registers.c, false),
+            ArithmeticTarget::D => cpu.registers.a = add(cpu, cpu.registers.d, false),
+            ArithmeticTarget::E => cpu.registers.a = add(cpu, cpu.registers.e, false),
+            ArithmeticTarget::H => cpu.registers.a = add(cpu, cpu.registers.h, false),
+            ArithmeticTarget::L => cpu.registers.a = add(cpu, cpu.registers.l, false),
+            ArithmeticTarget::HLI => {
+                let address = cpu.registers.get_hl();
+                let value = cpu.read_byte(address);
+                cpu.registers.a = add(cpu, value, false);
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
-        }
-
-        Instruction::ADC(target) => {
-            match target {
-                ArithmeticTarget::A => cpu.registers.a = add(cpu, cpu.registers.a, true),
-                ArithmeticTarget::B => cpu.registers.a = add(cpu, cpu.registers.b, true),
-                ArithmeticTarget::C => cpu.registers.a = add(cpu, cpu.registers.c, true),
-                ArithmeticTarget::D => cpu.registers.a = add(cpu, cpu.registers.d, true),
-                ArithmeticTarget::E => cpu.registers.a = add(cpu, cpu.registers.e, true),
-                ArithmeticTarget::H => cpu.registers.a = add(cpu, cpu.registers.h, true),
-                ArithmeticTarget::L => cpu.registers.a = add(cpu, cpu.registers.l, true),
-                ArithmeticTarget::HLI => {
-                    let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
-                    cpu.registers.a = add(cpu, value, true);
-                }
-                ArithmeticTarget::D8 => {
-                    let value = cpu.read_next_byte();
-                    cpu.registers.a = add(cpu, value, true);
-                }
+            ArithmeticTarget::D8 => {
+                let value = cpu.read_next_byte();
+                cpu.registers.a = add(cpu, value, false);
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
-        }
-
-        Instruction::SUB(target) => {
-            match target {
-                ArithmeticTarget::A => cpu.registers.a = sub(cpu, cpu.registers.a, false),
-                ArithmeticTarget::B => cpu.registers.a = sub(cpu, cpu.registers.b, false),
-                ArithmeticTarget::C => cpu.registers.a = sub(cpu, cpu.registers.c, false),
-                ArithmeticTarget::D => cpu.registers.a = sub(cpu, cpu.registers.d, false),
-                ArithmeticTarget::E => cpu.registers.a = sub(cpu, cpu.registers.e, false),
-                ArithmeticTarget::H => cpu.registers.a = sub(cpu, cpu.registers.h, false),
-                ArithmeticTarget::L => cpu.registers.a = sub(cpu, cpu.registers.l, false),
-                ArithmeticTarget::HLI => {
-                    let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
-                    cpu.registers.a = sub(cpu, value, false);
-                }
-                ArithmeticTarget::D8 => {
-                    let value = cpu.read_next_byte();
-                    cpu.registers.a = sub(cpu, value, false);
-                }
+        },
+
+        Instruction::ADC(target) => match target {
+            ArithmeticTarget::A => cpu.registers.a = add(cpu, cpu.registers.a, true),
+            ArithmeticTarget::B => cpu.registers.a = add(cpu, cpu.registers.b, true),
+            ArithmeticTarget::C => cpu.registers.a = add(cpu, cpu.registers.c, true),
+            ArithmeticTarget::D => cpu.registers.a = add(cpu, cpu.registers.d, true),
+            ArithmeticTarget::E => cpu.registers.a = add(cpu, cpu.registers.e, true),
+            ArithmeticTarget::H => cpu.registers.a = add(cpu, cpu.registers.h, true),
+            ArithmeticTarget::L => cpu.registers.a = add(cpu, cpu.registers.l, true),
+            ArithmeticTarget::HLI => {
+                let address = cpu.registers.get_hl();
+                let value = cpu.read_byte(address);
+                cpu.registers.a = add(cpu, value, true);
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
-        }
-
-        Instruction::SBC(target) => {
-            match target {
-                ArithmeticTarget::A => cpu.registers.a = sub(cpu, cpu.registers.a, true),
-                ArithmeticTarget::B => cpu.registers.a = sub(cpu, cpu.registers.b, true),
-                ArithmeticTarget::C => cpu.registers.a = sub(cpu, cpu.registers.c, true),
-                ArithmeticTarget::D => cpu.registers.a = sub(cpu, cpu.registers.d, true),
-                ArithmeticTarget::E => cpu.registers.a = sub(cpu, cpu.registers.e, true),
-                ArithmeticTarget::H => cpu.registers.a = sub(cpu, cpu.registers.h, true),
-                ArithmeticTarget::L => cpu.registers.a = sub(cpu, cpu.registers.l, true),
-                ArithmeticTarget::HLI => {
-                    let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
-                    cpu.registers.a = sub(cpu, value, true);
-                }
-                ArithmeticTarget::D8 => {
-                    let value = cpu.read_next_byte();
-                    cpu.registers.a = sub(cpu, value, true);
-                }
+            ArithmeticTarget::D8 => {
+                let value = cpu.read_next_byte();
+                cpu.registers.a = add(cpu, value, true);
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
-        }
+        },
+
+        Instruction::SUB(target) => match target {
+            ArithmeticTarget::A => cpu.registers.a = sub(cpu, cpu.registers.a, false),
+            ArithmeticTarget::B => cpu.registers.a = sub(cpu, cpu.registers.b, false),
+            ArithmeticTarget::C => cpu.registers.a = sub(cpu, cpu.registers.c, false),
+            ArithmeticTarget::D => cpu.registers.a = sub(cpu, cpu.registers.d, false),
+            ArithmeticTarget::E => cpu.registers.a = sub(cpu, cpu.registers.e, false),
+            ArithmeticTarget::H => cpu.registers.a = sub(cpu, cpu.registers.h, false),
+            ArithmeticTarget::L => cpu.registers.a = sub(cpu, cpu.registers.l, false),
+            ArithmeticTarget::HLI => {
+                let address = cpu.registers.get_hl();
+                let value = cpu.read_byte(address);
+                cpu.registers.a = sub(cpu, value, false);
+            }
+            ArithmeticTarget::D8 => {
+                let value = cpu.read_next_byte();
+                cpu.registers.a = sub(cpu, value, false);
+            }
+        },
+
+        Instruction::SBC(target) => match target {
+            ArithmeticTarget::A => cpu.registers.a = sub(cpu, cpu.registers.a, true),
+            ArithmeticTarget::B => cpu.registers.a = sub(cpu, cpu.registers.b, true),
+            ArithmeticTarget::C => cpu.registers.a = sub(cpu, cpu.registers.c, true),
+            ArithmeticTarget::D => cpu.registers.a = sub(cpu, cpu.registers.d, true),
+            ArithmeticTarget::E => cpu.registers.a = sub(cpu, cpu.registers.e, true),
+            ArithmeticTarget::H => cpu.registers.a = sub(cpu, cpu.registers.h, true),
+            ArithmeticTarget::L => cpu.registers.a = sub(cpu, cpu.registers.l, true),
+            ArithmeticTarget::HLI => {
+                let address = cpu.registers.get_hl();
+                let value = cpu.read_byte(address);
+                cpu.registers.a = sub(cpu, value, true);
+            }
+            ArithmeticTarget::D8 => {
+                let value = cpu.read_next_byte();
+                cpu.registers.a = sub(cpu, value, true);
+            }
+        },
 
         Instruction::AND(target) => {
             match target {
@@ -130,7 +99,7 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 ArithmeticTarget::L => cpu.registers.a &= cpu.registers.l,
                 ArithmeticTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     cpu.registers.a &= value;
                 }
                 ArithmeticTarget::D8 => {
@@ -138,12 +107,7 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                     cpu.registers.a &= value;
                 }
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
+            set_logic_flags(cpu, true);
         }
 
         Instruction::XOR(target) => {
@@ -157,7 +121,7 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 ArithmeticTarget::L => cpu.registers.a ^= cpu.registers.l,
                 ArithmeticTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     cpu.registers.a ^= value;
                 }
                 ArithmeticTarget::D8 => {
@@ -165,12 +129,7 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                     cpu.registers.a ^= value;
                 }
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
+            set_logic_flags(cpu, false);
         }
 
         Instruction::OR(target) => {
@@ -184,7 +143,7 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 ArithmeticTarget::L => cpu.registers.a |= cpu.registers.l,
                 ArithmeticTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     cpu.registers.a |= value;
                 }
                 ArithmeticTarget::D8 => {
@@ -192,157 +151,119 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                     cpu.registers.a |= value;
                 }
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
+            set_logic_flags(cpu, false);
         }
 
         // like SUB but the result is discarded
-        Instruction::CP(target) => {
-            match target {
-                ArithmeticTarget::A => _ = sub(cpu, cpu.registers.a, false),
-                ArithmeticTarget::B => _ = sub(cpu, cpu.registers.b, false),
-                ArithmeticTarget::C => _ = sub(cpu, cpu.registers.c, false),
-                ArithmeticTarget::D => _ = sub(cpu, cpu.registers.d, false),
-                ArithmeticTarget::E => _ = sub(cpu, cpu.registers.e, false),
-                ArithmeticTarget::H => _ = sub(cpu, cpu.registers.h, false),
-                ArithmeticTarget::L => _ = sub(cpu, cpu.registers.l, false),
-                ArithmeticTarget::HLI => {
-                    let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
-                    _ = sub(cpu, value, false);
-                }
-                ArithmeticTarget::D8 => {
-                    let value = cpu.read_next_byte();
-                    _ = sub(cpu, value, false);
-                }
+        Instruction::CP(target) => match target {
+            ArithmeticTarget::A => _ = sub(cpu, cpu.registers.a, false),
+            ArithmeticTarget::B => _ = sub(cpu, cpu.registers.b, false),
+            ArithmeticTarget::C => _ = sub(cpu, cpu.registers.c, false),
+            ArithmeticTarget::D => _ = sub(cpu, cpu.registers.d, false),
+            ArithmeticTarget::E => _ = sub(cpu, cpu.registers.e, false),
+            ArithmeticTarget::H => _ = sub(cpu, cpu.registers.h, false),
+            ArithmeticTarget::L => _ = sub(cpu, cpu.registers.l, false),
+            ArithmeticTarget::HLI => {
+                let address = cpu.registers.get_hl();
+                let value = cpu.read_byte(address);
+                _ = sub(cpu, value, false);
             }
-            let (pc, cycles) = match target {
-                ArithmeticTarget::HLI => (1, 8),
-                ArithmeticTarget::D8 => (2, 8),
-                _ => (1, 4),
-            };
-            (cpu.pc.wrapping_add(pc), cycles)
-        }
-
-        Instruction::INC(target) => {
-            match target {
-                IncDecTarget::A => cpu.registers.a = inc_8bit(cpu, cpu.registers.a),
-                IncDecTarget::B => cpu.registers.b = inc_8bit(cpu, cpu.registers.b),
-                IncDecTarget::C => cpu.registers.c = inc_8bit(cpu, cpu.registers.c),
-                IncDecTarget::D => cpu.registers.d = inc_8bit(cpu, cpu.registers.d),
-                IncDecTarget::E => cpu.registers.e = inc_8bit(cpu, cpu.registers.e),
-                IncDecTarget::H => cpu.registers.h = inc_8bit(cpu, cpu.registers.h),
-                IncDecTarget::L => cpu.registers.l = inc_8bit(cpu, cpu.registers.l),
-                IncDecTarget::HLI => {
-                    let hl = cpu.registers.get_hl();
-                    let amount = cpu.bus.read_byte(hl);
-                    let result = inc_8bit(cpu, amount);
-                    cpu.bus.write_byte(hl, result);
-                }
-                IncDecTarget::BC => {
-                    let value = cpu.registers.get_bc().wrapping_add(1);
-                    cpu.registers.set_bc(value);
-                }
-                IncDecTarget::DE => {
-                    let value = cpu.registers.get_de().wrapping_add(1);
-                    cpu.registers.set_de(value);
-                }
-                IncDecTarget::HL => {
-                    let value = cpu.registers.get_hl().wrapping_add(1);
-                    cpu.registers.set_hl(value);
-                }
-                IncDecTarget::SP => {
-                    let amount = cpu.sp;
-                    let result = cpu.sp.wrapping_add(amount);
-                    cpu.sp = result;
-                }
+            ArithmeticTarget::D8 => {
+                let value = cpu.read_next_byte();
+                _ = sub(cpu, value, false);
             }
-            let cycles = match target {
-                IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => 8,
-                IncDecTarget::HLI => 12,
-                _ => 4,
-            };
-            (cpu.pc.wrapping_add(1), cycles)
-        }
-
-        Instruction::DEC(target) => {
-            match target {
-                IncDecTarget::A => cpu.registers.a = dec_8bit(cpu, cpu.registers.a),
-                IncDecTarget::B => cpu.registers.b = dec_8bit(cpu, cpu.registers.b),
-                IncDecTarget::C => cpu.registers.c = dec_8bit(cpu, cpu.registers.c),
-                IncDecTarget::D => cpu.registers.d = dec_8bit(cpu, cpu.registers.d),
-                IncDecTarget::E => cpu.registers.e = dec_8bit(cpu, cpu.registers.e),
-                IncDecTarget::H => cpu.registers.h = dec_8bit(cpu, cpu.registers.h),
-                IncDecTarget::L => cpu.registers.l = dec_8bit(cpu, cpu.registers.l),
-                IncDecTarget::HLI => {
-                    let hl = cpu.registers.get_hl();
-                    let amount = cpu.bus.read_byte(hl);
-                    let result = dec_8bit(cpu, amount);
-                    cpu.bus.write_byte(hl, result);
-                }
-                IncDecTarget::BC => {
-                    let value = cpu.registers.get_bc().wrapping_sub(1);
-                    cpu.registers.set_bc(value);
-                }
-                IncDecTarget::DE => {
-                    let value = cpu.registers.get_de().wrapping_sub(1);
-                    cpu.registers.set_de(value);
-                }
-                IncDecTarget::HL => {
-                    let value = cpu.registers.get_hl().wrapping_sub(1);
-                    cpu.registers.set_hl(value);
-                }
-                IncDecTarget::SP => {
-                    let amount = cpu.sp;
-                    let result = cpu.sp.wrapping_sub(amount);
-                    cpu.sp = result;
-                }
+        },
+
+        Instruction::INC(target) => match target {
+            IncDecTarget::A => cpu.registers.a = inc_8bit(cpu, cpu.registers.a),
+            IncDecTarget::B => cpu.registers.b = inc_8bit(cpu, cpu.registers.b),
+            IncDecTarget::C => cpu.registers.c = inc_8bit(cpu, cpu.registers.c),
+            IncDecTarget::D => cpu.registers.d = inc_8bit(cpu, cpu.registers.d),
+            IncDecTarget::E => cpu.registers.e = inc_8bit(cpu, cpu.registers.e),
+            IncDecTarget::H => cpu.registers.h = inc_8bit(cpu, cpu.registers.h),
+            IncDecTarget::L => cpu.registers.l = inc_8bit(cpu, cpu.registers.l),
+            IncDecTarget::HLI => {
+                let hl = cpu.registers.get_hl();
+                let amount = cpu.read_byte(hl);
+                let result = inc_8bit(cpu, amount);
+                cpu.write_byte(hl, result);
             }
-            let cycles = match target {
-                IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => 8,
-                IncDecTarget::HLI => 12,
-                _ => 4,
-            };
-            (cpu.pc.wrapping_add(1), cycles)
-        }
+            IncDecTarget::BC => {
+                let value = cpu.registers.get_bc().wrapping_add(1);
+                cpu.registers.set_bc(value);
+            }
+            IncDecTarget::DE => {
+                let value = cpu.registers.get_de().wrapping_add(1);
+                cpu.registers.set_de(value);
+            }
+            IncDecTarget::HL => {
+                let value = cpu.registers.get_hl().wrapping_add(1);
+                cpu.registers.set_hl(value);
+            }
+            IncDecTarget::SP => {
+                cpu.sp = cpu.sp.wrapping_add(1);
+            }
+        },
+
+        Instruction::DEC(target) => match target {
+            IncDecTarget::A => cpu.registers.a = dec_8bit(cpu, cpu.registers.a),
+            IncDecTarget::B => cpu.registers.b = dec_8bit(cpu, cpu.registers.b),
+            IncDecTarget::C => cpu.registers.c = dec_8bit(cpu, cpu.registers.c),
+            IncDecTarget::D => cpu.registers.d = dec_8bit(cpu, cpu.registers.d),
+            IncDecTarget::E => cpu.registers.e = dec_8bit(cpu, cpu.registers.e),
+            IncDecTarget::H => cpu.registers.h = dec_8bit(cpu, cpu.registers.h),
+            IncDecTarget::L => cpu.registers.l = dec_8bit(cpu, cpu.registers.l),
+            IncDecTarget::HLI => {
+                let hl = cpu.registers.get_hl();
+                let amount = cpu.read_byte(hl);
+                let result = dec_8bit(cpu, amount);
+                cpu.write_byte(hl, result);
+            }
+            IncDecTarget::BC => {
+                let value = cpu.registers.get_bc().wrapping_sub(1);
+                cpu.registers.set_bc(value);
+            }
+            IncDecTarget::DE => {
+                let value = cpu.registers.get_de().wrapping_sub(1);
+                cpu.registers.set_de(value);
+            }
+            IncDecTarget::HL => {
+                let value = cpu.registers.get_hl().wrapping_sub(1);
+                cpu.registers.set_hl(value);
+            }
+            IncDecTarget::SP => {
+                cpu.sp = cpu.sp.wrapping_sub(1);
+            }
+        },
 
         Instruction::DAA => {
             cpu.registers.a = decimal_adjust(cpu, cpu.registers.a);
-            (cpu.pc.wrapping_add(1), 4)
         }
 
         Instruction::CPL => {
             cpu.registers.a = !cpu.registers.a;
             cpu.registers.f.subtract = true;
             cpu.registers.f.half_carry = true;
-            (cpu.pc.wrapping_add(1), 4)
         }
 
-        Instruction::ADDHL(target) => {
-            match target {
-                ADDHLTarget::BC => {
-                    let value = add_hl(cpu, cpu.registers.get_bc());
-                    cpu.registers.set_hl(value);
-                }
-                ADDHLTarget::DE => {
-                    let value = add_hl(cpu, cpu.registers.get_de());
-                    cpu.registers.set_hl(value);
-                }
-                ADDHLTarget::HL => {
-                    let value = add_hl(cpu, cpu.registers.get_hl());
-                    cpu.registers.set_hl(value);
-                }
-                ADDHLTarget::SP => {
-                    let value = add_hl(cpu, cpu.registers.get_hl());
-                    cpu.sp = value;
-                }
+        Instruction::ADDHL(target) => match target {
+            ADDHLTarget::BC => {
+                let value = add_hl(cpu, cpu.registers.get_bc());
+                cpu.registers.set_hl(value);
             }
-            (cpu.pc.wrapping_add(1), 8)
-        }
+            ADDHLTarget::DE => {
+                let value = add_hl(cpu, cpu.registers.get_de());
+                cpu.registers.set_hl(value);
+            }
+            ADDHLTarget::HL => {
+                let value = add_hl(cpu, cpu.registers.get_hl());
+                cpu.registers.set_hl(value);
+            }
+            ADDHLTarget::SP => {
+                let value = add_hl(cpu, cpu.sp);
+                cpu.registers.set_hl(value);
+            }
+        },
 
         Instruction::ADDSP => {
             // DESCRIPTION: (add stack pointer) - add a one byte signed number to
@@ -368,15 +289,12 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
             cpu.registers.f.subtract = false;
 
             cpu.sp = result;
-
-            (cpu.pc.wrapping_add(2), 16)
         }
 
         Instruction::CCF => {
             cpu.registers.f.subtract = false;
             cpu.registers.f.half_carry = false;
             cpu.registers.f.carry = !cpu.registers.f.carry;
-            (cpu.pc.wrapping_add(1), 4)
         }
 
         Instruction::SCF => {
@@ -387,17 +305,13 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
             cpu.registers.f.subtract = false;
             cpu.registers.f.half_carry = false;
             cpu.registers.f.carry = true;
-            (cpu.pc.wrapping_add(1), 4)
         }
 
-        _ => {
-            /*ignore other instructions*/
-            (0, 0)
-        }
+        _ => { /*ignore other instructions*/ }
     }
 }
 
-fn add(cpu: &mut CPU, value: u8, with_carry: bool) -> u8 {
+fn add<B: Bus>(cpu: &mut CPU<B>, value: u8, with_carry: bool) -> u8 {
     let additional_carry = if with_carry && cpu.registers.f.carry {
         1
     } else {
@@ -418,7 +332,7 @@ fn add(cpu: &mut CPU, value: u8, with_carry: bool) -> u8 {
     new_value2
 }
 
-fn sub(cpu: &mut CPU, value: u8, with_carry: bool) -> u8 {
+fn sub<B: Bus>(cpu: &mut CPU<B>, value: u8, with_carry: bool) -> u8 {
     let additional_carry = if with_carry && cpu.registers.f.carry {
         1
     } else {
@@ -439,7 +353,7 @@ fn sub(cpu: &mut CPU, value: u8, with_carry: bool) -> u8 {
     new_value2
 }
 
-fn inc_8bit(cpu: &mut CPU, value: u8) -> u8 {
+fn inc_8bit<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let new_value = value.wrapping_add(1);
     cpu.registers.f.zero = new_value == 0;
     cpu.registers.f.subtract = false;
@@ -450,7 +364,7 @@ fn inc_8bit(cpu: &mut CPU, value: u8) -> u8 {
     new_value
 }
 
-fn dec_8bit(cpu: &mut CPU, value: u8) -> u8 {
+fn dec_8bit<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let new_value = value.wrapping_sub(1);
     cpu.registers.f.zero = new_value == 0;
     cpu.registers.f.subtract = true;
@@ -461,7 +375,7 @@ fn dec_8bit(cpu: &mut CPU, value: u8) -> u8 {
     new_value
 }
 
-fn decimal_adjust(cpu: &mut CPU, value: u8) -> u8 {
+fn decimal_adjust<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let flags = cpu.registers.f;
     let mut carry = false;
 
@@ -492,14 +406,25 @@ fn decimal_adjust(cpu: &mut CPU, value: u8) -> u8 {
     result
 }
 
-fn add_hl(cpu: &mut CPU, value: u16) -> u16 {
-    let (new_value, did_overflow) = cpu.registers.get_hl().overflowing_add(value);
-    cpu.registers.f.zero = new_value == 0;
+fn add_hl<B: Bus>(cpu: &mut CPU<B>, value: u16) -> u16 {
+    let hl = cpu.registers.get_hl();
+    let (new_value, did_overflow) = hl.overflowing_add(value);
+    // the zero flag is left untouched by 16 bit additions
     cpu.registers.f.subtract = false;
     cpu.registers.f.carry = did_overflow;
+    // Half Carry is computed on the carry from bit 11 into bit 12
+    cpu.registers.f.half_carry = (hl & 0xFFF) + (value & 0xFFF) > 0xFFF;
     new_value
 }
 
+fn set_logic_flags<B: Bus>(cpu: &mut CPU<B>, half_carry: bool) {
+    // AND, XOR and OR only compute the zero flag, AND always sets half carry
+    cpu.registers.f.zero = cpu.registers.a == 0;
+    cpu.registers.f.subtract = false;
+    cpu.registers.f.half_carry = half_carry;
+    cpu.registers.f.carry = false;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -570,4 +495,83 @@ mod tests {
         assert!(cpu.registers.f.carry);
         assert_eq!(cpu.registers.get_hl(), 0x0002);
     }
+
+    #[test]
+    fn and_sets_zero_and_half_carry() {
+        let mut cpu = CPU::new();
+        cpu.registers.a = 0b1010_0000;
+        cpu.registers.b = 0b0101_0000;
+        cpu.registers.f.carry = true;
+        execute(&mut cpu, Instruction::AND(ArithmeticTarget::B));
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn add_hl_to_sp_keeps_zero_flag() {
+        let mut cpu = CPU::new();
+        cpu.registers.set_hl(0x0FFF);
+        cpu.sp = 0x0001;
+        cpu.registers.f.zero = true;
+        execute(&mut cpu, Instruction::ADDHL(ADDHLTarget::SP));
+        assert_eq!(cpu.registers.get_hl(), 0x1000);
+        assert_eq!(cpu.sp, 0x0001);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn xor_and_or_clear_the_other_flags() {
+        let mut cpu = CPU::new();
+        cpu.registers.a = 0x5A;
+        cpu.registers.f.half_carry = true;
+        cpu.registers.f.carry = true;
+        execute(&mut cpu, Instruction::XOR(ArithmeticTarget::A));
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+
+        cpu.registers.b = 0x01;
+        cpu.registers.f.subtract = true;
+        execute(&mut cpu, Instruction::OR(ArithmeticTarget::B));
+        assert_eq!(cpu.registers.a, 0x01);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+    }
+
+    #[test]
+    fn inc_and_dec_sp_step_by_one() {
+        let mut cpu = CPU::new();
+        cpu.sp = 0xFFFF;
+        cpu.registers.f.zero = true;
+        execute(&mut cpu, Instruction::INC(IncDecTarget::SP));
+        assert_eq!(cpu.sp, 0x0000);
+        execute(&mut cpu, Instruction::DEC(IncDecTarget::SP));
+        execute(&mut cpu, Instruction::DEC(IncDecTarget::SP));
+        assert_eq!(cpu.sp, 0xFFFE);
+        // 16 bit increments don't touch the flags
+        assert!(cpu.registers.f.zero);
+    }
+
+    #[test]
+    fn add_hl_half_carry_is_from_bit_11() {
+        let mut cpu = CPU::new();
+        cpu.registers.set_hl(0x00FF);
+        cpu.registers.set_de(0x0001);
+        cpu.registers.f.half_carry = true;
+        execute(&mut cpu, Instruction::ADDHL(ADDHLTarget::DE));
+        assert_eq!(cpu.registers.get_hl(), 0x0100);
+        assert!(!cpu.registers.f.half_carry);
+
+        cpu.registers.set_hl(0x8000);
+        cpu.registers.set_de(0x8000);
+        cpu.registers.f.zero = false;
+        execute(&mut cpu, Instruction::ADDHL(ADDHLTarget::DE));
+        assert_eq!(cpu.registers.get_hl(), 0x0000);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.zero);
+    }
 }
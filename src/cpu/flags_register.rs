@@ -46,6 +46,12 @@ impl FlagsRegister {
     }
 }
 
+impl Default for FlagsRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,9 +67,9 @@ mod tests {
     #[test]
     fn can_be_converted_from_u8() {
         let result: FlagsRegister = 0b1001_0000.into();
-        assert_eq!(result.zero, true);
-        assert_eq!(result.carry, true);
-        assert_eq!(result.half_carry, false);
-        assert_eq!(result.subtract, false);
+        assert!(result.zero);
+        assert!(result.carry);
+        assert!(!result.half_carry);
+        assert!(!result.subtract);
     }
 }
@@ -1,3 +1,12 @@
+mod decode_table;
+mod display;
+mod encoder;
+mod metadata;
+
+pub use decode_table::DecodedInstruction;
+pub use display::InstructionWithOperands;
+pub use metadata::{FlagEffect, FlagEffects};
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Instruction {
     // http://bgb.bircd.org/pandocs.htm#cpuinstructionset
@@ -63,6 +72,7 @@ pub enum Instruction {
 
     // System management
     HALT,
+    STOP,
     NOP,
     DI,
     EI,
@@ -245,7 +255,7 @@ impl RSTLocation {
 
 /* --- OPCODES --- */
 impl Instruction {
-    pub fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
+    pub const fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
         if prefixed {
             Instruction::from_byte_prefixed(byte)
         } else {
@@ -253,7 +263,7 @@ impl Instruction {
         }
     }
 
-    fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
+    const fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
         match byte {
             0x00 => Some(Instruction::RLC(PrefixTarget::B)),
             0x01 => Some(Instruction::RLC(PrefixTarget::C)),
@@ -545,7 +555,7 @@ impl Instruction {
         }
     }
 
-    fn from_byte_not_prefixed(byte: u8) -> Option<Instruction> {
+    const fn from_byte_not_prefixed(byte: u8) -> Option<Instruction> {
         match byte {
             0x3c => Some(Instruction::INC(IncDecTarget::A)),
             0x04 => Some(Instruction::INC(IncDecTarget::B)),
@@ -1061,6 +1071,7 @@ impl Instruction {
 
             0x00 => Some(Instruction::NOP),
             0x76 => Some(Instruction::HALT),
+            0x10 => Some(Instruction::STOP),
             0xf3 => Some(Instruction::DI),
             0xfb => Some(Instruction::EI),
 
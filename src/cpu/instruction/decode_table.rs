@@ -0,0 +1,82 @@
+use super::Instruction;
+
+/// Instruction decoded ahead of time together with the metadata the CPU
+/// needs to execute it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DecodedInstruction {
+    pub instruction: Instruction,
+    pub size: u16,
+    pub cycles: u8,        // cycles when no branch is taken
+    pub branch_cycles: u8, // cycles when a jump, call or return is taken
+}
+
+impl DecodedInstruction {
+    pub const fn new(instruction: Instruction) -> DecodedInstruction {
+        DecodedInstruction {
+            instruction,
+            size: instruction.size(),
+            cycles: instruction.cycles(),
+            branch_cycles: match instruction.branch_cycles() {
+                Some(cycles) => cycles,
+                None => instruction.cycles(),
+            },
+        }
+    }
+}
+
+type DecodeTable = [Option<DecodedInstruction>; 256];
+
+// Both tables are filled at compile time from `Instruction::from_byte`, so
+// they can't disagree with it.
+const fn build_table(prefixed: bool) -> DecodeTable {
+    let mut table = [None; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        table[byte] = match Instruction::from_byte(byte as u8, prefixed) {
+            Some(instruction) => Some(DecodedInstruction::new(instruction)),
+            None => None,
+        };
+        byte += 1;
+    }
+    table
+}
+
+static UNPREFIXED_TABLE: DecodeTable = build_table(false);
+static PREFIXED_TABLE: DecodeTable = build_table(true);
+
+impl Instruction {
+    /// Same as `from_byte` but looks the opcode up in a precomputed table,
+    /// which is what the CPU uses on every step.
+    pub fn decode(byte: u8, prefixed: bool) -> Option<&'static DecodedInstruction> {
+        let table = if prefixed {
+            &PREFIXED_TABLE
+        } else {
+            &UNPREFIXED_TABLE
+        };
+        table[byte as usize].as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_match_from_byte() {
+        for prefixed in [false, true] {
+            for byte in 0..=0xFFu8 {
+                let decoded = Instruction::decode(byte, prefixed);
+                let instruction = Instruction::from_byte(byte, prefixed);
+                assert_eq!(decoded.map(|d| d.instruction), instruction);
+                if let (Some(decoded), Some(instruction)) = (decoded, instruction) {
+                    assert_eq!(decoded.size, instruction.size());
+                    assert_eq!(decoded.cycles, instruction.cycles());
+                    assert_eq!(
+                        decoded.branch_cycles,
+                        instruction.branch_cycles().unwrap_or(instruction.cycles())
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+use std::fmt;
+
+use super::{ADDHLTarget, ArithmeticTarget, BitPosition, IncDecTarget, Indirect, Instruction};
+use super::{JumpTest, LoadByteSource, LoadByteTarget, LoadType, LoadWordTarget};
+use super::{PrefixTarget, RSTLocation, StackTarget};
+
+// Mnemonics follow the RGBDS syntax. Without the bytes following the opcode,
+// immediate operands are printed with the placeholders used by the RGBDS
+// documentation: n8, n16, a8 (LDH address), a16 and e8.
+
+impl fmt::Display for ArithmeticTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            ArithmeticTarget::A => "A",
+            ArithmeticTarget::B => "B",
+            ArithmeticTarget::C => "C",
+            ArithmeticTarget::D => "D",
+            ArithmeticTarget::E => "E",
+            ArithmeticTarget::H => "H",
+            ArithmeticTarget::L => "L",
+            ArithmeticTarget::HLI => "[HL]",
+            ArithmeticTarget::D8 => "n8",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for IncDecTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            IncDecTarget::A => "A",
+            IncDecTarget::B => "B",
+            IncDecTarget::C => "C",
+            IncDecTarget::D => "D",
+            IncDecTarget::E => "E",
+            IncDecTarget::H => "H",
+            IncDecTarget::L => "L",
+            IncDecTarget::HLI => "[HL]",
+            IncDecTarget::BC => "BC",
+            IncDecTarget::DE => "DE",
+            IncDecTarget::HL => "HL",
+            IncDecTarget::SP => "SP",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for ADDHLTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            ADDHLTarget::BC => "BC",
+            ADDHLTarget::DE => "DE",
+            ADDHLTarget::HL => "HL",
+            ADDHLTarget::SP => "SP",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for JumpTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // unconditional jumps have no condition operand
+        let condition = match self {
+            JumpTest::NotZero => "NZ",
+            JumpTest::Zero => "Z",
+            JumpTest::NotCarry => "NC",
+            JumpTest::Carry => "C",
+            JumpTest::Always => "",
+        };
+        f.write_str(condition)
+    }
+}
+
+impl fmt::Display for LoadByteTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            LoadByteTarget::A => "A",
+            LoadByteTarget::B => "B",
+            LoadByteTarget::C => "C",
+            LoadByteTarget::D => "D",
+            LoadByteTarget::E => "E",
+            LoadByteTarget::H => "H",
+            LoadByteTarget::L => "L",
+            LoadByteTarget::HLI => "[HL]",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for LoadByteSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            LoadByteSource::A => "A",
+            LoadByteSource::B => "B",
+            LoadByteSource::C => "C",
+            LoadByteSource::D => "D",
+            LoadByteSource::E => "E",
+            LoadByteSource::H => "H",
+            LoadByteSource::L => "L",
+            LoadByteSource::D8 => "n8",
+            LoadByteSource::HLI => "[HL]",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for LoadWordTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            LoadWordTarget::BC => "BC",
+            LoadWordTarget::DE => "DE",
+            LoadWordTarget::HL => "HL",
+            LoadWordTarget::SP => "SP",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for Indirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            Indirect::BCIndirect => "[BC]",
+            Indirect::DEIndirect => "[DE]",
+            Indirect::HLIndirectMinus => "[HL-]",
+            Indirect::HLIndirectPlus => "[HL+]",
+            Indirect::WordIndirect => "[a16]",
+            Indirect::LastByteIndirect => "[C]",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for LoadType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_load(f, self, None)
+    }
+}
+
+impl fmt::Display for StackTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            StackTarget::AF => "AF",
+            StackTarget::BC => "BC",
+            StackTarget::DE => "DE",
+            StackTarget::HL => "HL",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for PrefixTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = match self {
+            PrefixTarget::A => "A",
+            PrefixTarget::B => "B",
+            PrefixTarget::C => "C",
+            PrefixTarget::D => "D",
+            PrefixTarget::E => "E",
+            PrefixTarget::H => "H",
+            PrefixTarget::L => "L",
+            PrefixTarget::HLI => "[HL]",
+        };
+        f.write_str(operand)
+    }
+}
+
+impl fmt::Display for BitPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", u8::from(*self))
+    }
+}
+
+impl fmt::Display for RSTLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${:02X}", self.to_hex())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_instruction(f, self, None)
+    }
+}
+
+/// Displays an instruction with the values of its immediate operands
+/// instead of placeholders, see `Instruction::with_operands`.
+pub struct InstructionWithOperands<'a> {
+    instruction: Instruction,
+    operands: Operands<'a>,
+}
+
+impl fmt::Display for InstructionWithOperands<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_instruction(f, &self.instruction, Some(&self.operands))
+    }
+}
+
+impl Instruction {
+    /// Formats the instruction stored at `address` with its immediate
+    /// operands filled in. `operands` are the bytes following the opcode,
+    /// placeholders are kept if there are not enough of them.
+    pub fn with_operands(self, address: u16, operands: &[u8]) -> InstructionWithOperands<'_> {
+        InstructionWithOperands {
+            instruction: self,
+            operands: Operands {
+                address,
+                bytes: operands,
+            },
+        }
+    }
+}
+
+struct Operands<'a> {
+    address: u16, // address of the opcode, used to resolve relative jumps
+    bytes: &'a [u8],
+}
+
+#[derive(Copy, Clone)]
+enum Immediate {
+    Byte,         // n8
+    Word,         // n16
+    HighAddress,  // a8, offset from 0xFF00 used by LDH
+    Address,      // a16
+    RelativeJump, // e8 added to the address of the next instruction
+    StackOffset,  // e8 added to SP
+}
+
+impl Immediate {
+    fn placeholder(self) -> &'static str {
+        match self {
+            Immediate::Byte => "n8",
+            Immediate::Word => "n16",
+            Immediate::HighAddress => "a8",
+            Immediate::Address => "a16",
+            Immediate::RelativeJump | Immediate::StackOffset => "e8",
+        }
+    }
+}
+
+fn write_immediate(
+    f: &mut fmt::Formatter,
+    immediate: Immediate,
+    operands: Option<&Operands>,
+) -> fmt::Result {
+    let bytes = operands.map(|operands| operands.bytes).unwrap_or(&[]);
+    match (immediate, bytes) {
+        (Immediate::Byte, [value, ..]) => write!(f, "${:02X}", value),
+        (Immediate::HighAddress, [value, ..]) => write!(f, "$FF{:02X}", value),
+        (Immediate::Word | Immediate::Address, [low, high, ..]) => {
+            write!(f, "${:04X}", u16::from_le_bytes([*low, *high]))
+        }
+        (Immediate::RelativeJump, [offset, ..]) => {
+            // relative jumps are 2 bytes wide
+            let next = operands.unwrap().address.wrapping_add(2);
+            write!(f, "${:04X}", next.wrapping_add(*offset as i8 as u16))
+        }
+        (Immediate::StackOffset, [offset, ..]) => write!(f, "{}", *offset as i8),
+        _ => f.write_str(immediate.placeholder()),
+    }
+}
+
+fn write_arithmetic(
+    f: &mut fmt::Formatter,
+    mnemonic: &str,
+    target: &ArithmeticTarget,
+    operands: Option<&Operands>,
+) -> fmt::Result {
+    write!(f, "{} A, ", mnemonic)?;
+    match target {
+        ArithmeticTarget::D8 => write_immediate(f, Immediate::Byte, operands),
+        _ => write!(f, "{}", target),
+    }
+}
+
+fn write_condition(f: &mut fmt::Formatter, mnemonic: &str, test: &JumpTest) -> fmt::Result {
+    match test {
+        JumpTest::Always => write!(f, "{} ", mnemonic),
+        _ => write!(f, "{} {}, ", mnemonic, test),
+    }
+}
+
+fn write_load(
+    f: &mut fmt::Formatter,
+    load_type: &LoadType,
+    operands: Option<&Operands>,
+) -> fmt::Result {
+    match load_type {
+        LoadType::Byte(target, LoadByteSource::D8) => {
+            write!(f, "LD {}, ", target)?;
+            write_immediate(f, Immediate::Byte, operands)
+        }
+        LoadType::Byte(target, source) => write!(f, "LD {}, {}", target, source),
+        LoadType::Word(target) => {
+            write!(f, "LD {}, ", target)?;
+            write_immediate(f, Immediate::Word, operands)
+        }
+        LoadType::AFromIndirect(Indirect::WordIndirect) => {
+            f.write_str("LD A, [")?;
+            write_immediate(f, Immediate::Address, operands)?;
+            f.write_str("]")
+        }
+        LoadType::AFromIndirect(Indirect::LastByteIndirect) => f.write_str("LDH A, [C]"),
+        LoadType::AFromIndirect(indirect) => write!(f, "LD A, {}", indirect),
+        LoadType::IndirectFromA(Indirect::WordIndirect) => {
+            f.write_str("LD [")?;
+            write_immediate(f, Immediate::Address, operands)?;
+            f.write_str("], A")
+        }
+        LoadType::IndirectFromA(Indirect::LastByteIndirect) => f.write_str("LDH [C], A"),
+        LoadType::IndirectFromA(indirect) => write!(f, "LD {}, A", indirect),
+        LoadType::AFromByteAddress => {
+            f.write_str("LDH A, [")?;
+            write_immediate(f, Immediate::HighAddress, operands)?;
+            f.write_str("]")
+        }
+        LoadType::ByteAddressFromA => {
+            f.write_str("LDH [")?;
+            write_immediate(f, Immediate::HighAddress, operands)?;
+            f.write_str("], A")
+        }
+        LoadType::SPFromHL => f.write_str("LD SP, HL"),
+        LoadType::HLFromSPN => {
+            f.write_str("LD HL, SP")?;
+            match operands.and_then(|operands| operands.bytes.first()) {
+                Some(offset) => write!(f, "{:+}", *offset as i8),
+                None => f.write_str("+e8"),
+            }
+        }
+        LoadType::IndirectFromSP => {
+            f.write_str("LD [")?;
+            write_immediate(f, Immediate::Address, operands)?;
+            f.write_str("], SP")
+        }
+    }
+}
+
+fn write_instruction(
+    f: &mut fmt::Formatter,
+    instruction: &Instruction,
+    operands: Option<&Operands>,
+) -> fmt::Result {
+    match instruction {
+        Instruction::ADD(target) => write_arithmetic(f, "ADD", target, operands),
+        Instruction::ADC(target) => write_arithmetic(f, "ADC", target, operands),
+        Instruction::SUB(target) => write_arithmetic(f, "SUB", target, operands),
+        Instruction::SBC(target) => write_arithmetic(f, "SBC", target, operands),
+        Instruction::AND(target) => write_arithmetic(f, "AND", target, operands),
+        Instruction::XOR(target) => write_arithmetic(f, "XOR", target, operands),
+        Instruction::OR(target) => write_arithmetic(f, "OR", target, operands),
+        Instruction::CP(target) => write_arithmetic(f, "CP", target, operands),
+        Instruction::INC(target) => write!(f, "INC {}", target),
+        Instruction::DEC(target) => write!(f, "DEC {}", target),
+        Instruction::DAA => f.write_str("DAA"),
+        Instruction::CPL => f.write_str("CPL"),
+
+        Instruction::ADDHL(target) => write!(f, "ADD HL, {}", target),
+        Instruction::ADDSP => {
+            f.write_str("ADD SP, ")?;
+            write_immediate(f, Immediate::StackOffset, operands)
+        }
+
+        Instruction::LD(load_type) => write_load(f, load_type, operands),
+
+        Instruction::JP(test) => {
+            write_condition(f, "JP", test)?;
+            write_immediate(f, Immediate::Address, operands)
+        }
+        Instruction::JR(test) => {
+            write_condition(f, "JR", test)?;
+            write_immediate(f, Immediate::RelativeJump, operands)
+        }
+        Instruction::JPI => f.write_str("JP HL"),
+
+        Instruction::PUSH(target) => write!(f, "PUSH {}", target),
+        Instruction::POP(target) => write!(f, "POP {}", target),
+        Instruction::CALL(test) => {
+            write_condition(f, "CALL", test)?;
+            write_immediate(f, Immediate::Address, operands)
+        }
+        Instruction::RET(JumpTest::Always) => f.write_str("RET"),
+        Instruction::RET(test) => write!(f, "RET {}", test),
+        Instruction::RETI => f.write_str("RETI"),
+        Instruction::RST(location) => write!(f, "RST {}", location),
+
+        Instruction::CCF => f.write_str("CCF"),
+        Instruction::SCF => f.write_str("SCF"),
+
+        Instruction::RRA => f.write_str("RRA"),
+        Instruction::RLA => f.write_str("RLA"),
+        Instruction::RRCA => f.write_str("RRCA"),
+        Instruction::RLCA => f.write_str("RLCA"),
+
+        Instruction::BIT(target, position) => write!(f, "BIT {}, {}", position, target),
+        Instruction::RES(target, position) => write!(f, "RES {}, {}", position, target),
+        Instruction::SET(target, position) => write!(f, "SET {}, {}", position, target),
+        Instruction::SRL(target) => write!(f, "SRL {}", target),
+        Instruction::RR(target) => write!(f, "RR {}", target),
+        Instruction::RL(target) => write!(f, "RL {}", target),
+        Instruction::RRC(target) => write!(f, "RRC {}", target),
+        Instruction::RLC(target) => write!(f, "RLC {}", target),
+        Instruction::SRA(target) => write!(f, "SRA {}", target),
+        Instruction::SLA(target) => write!(f, "SLA {}", target),
+        Instruction::SWAP(target) => write!(f, "SWAP {}", target),
+
+        Instruction::HALT => f.write_str("HALT"),
+        Instruction::STOP => f.write_str("STOP"),
+        Instruction::NOP => f.write_str("NOP"),
+        Instruction::DI => f.write_str("DI"),
+        Instruction::EI => f.write_str("EI"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_uses_placeholders_for_immediates() {
+        let instruction = Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::D8));
+        assert_eq!(instruction.to_string(), "LD B, n8");
+        assert_eq!(Instruction::JR(JumpTest::NotZero).to_string(), "JR NZ, e8");
+        assert_eq!(
+            Instruction::LD(LoadType::AFromIndirect(Indirect::HLIndirectPlus)).to_string(),
+            "LD A, [HL+]"
+        );
+        assert_eq!(
+            Instruction::BIT(PrefixTarget::HLI, BitPosition::B7).to_string(),
+            "BIT 7, [HL]"
+        );
+        assert_eq!(Instruction::RST(RSTLocation::X38).to_string(), "RST $38");
+    }
+
+    #[test]
+    fn with_operands_fills_in_immediates() {
+        let instruction = Instruction::LD(LoadType::Word(LoadWordTarget::SP));
+        assert_eq!(
+            instruction.with_operands(0x0100, &[0xFE, 0xFF]).to_string(),
+            "LD SP, $FFFE"
+        );
+        assert_eq!(
+            Instruction::LD(LoadType::ByteAddressFromA)
+                .with_operands(0x0100, &[0x40])
+                .to_string(),
+            "LDH [$FF40], A"
+        );
+        assert_eq!(
+            Instruction::LD(LoadType::HLFromSPN)
+                .with_operands(0x0100, &[0xFE])
+                .to_string(),
+            "LD HL, SP-2"
+        );
+        assert_eq!(
+            Instruction::CALL(JumpTest::Always)
+                .with_operands(0x0100, &[0x34])
+                .to_string(),
+            "CALL a16"
+        );
+    }
+
+    #[test]
+    fn relative_jumps_show_the_target_address() {
+        let instruction = Instruction::JR(JumpTest::Always);
+        assert_eq!(
+            instruction.with_operands(0x0150, &[0xFE]).to_string(),
+            "JR $0150"
+        );
+        assert_eq!(
+            instruction.with_operands(0x0150, &[0x10]).to_string(),
+            "JR $0162"
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use super::{ADDHLTarget, ArithmeticTarget, BitPosition, IncDecTarget, Indirect, Instruction};
+use super::{JumpTest, LoadByteSource, LoadByteTarget, LoadType, LoadWordTarget};
+use super::{PrefixTarget, StackTarget};
+
+// The opcodes are built from the bit fields of the SM83 encoding instead of
+// reusing the decoding tables, so the round-trip test can catch mistakes in
+// either of them.
+
+impl Instruction {
+    /// Encodes the instruction into `size()` bytes: the optional 0xCB prefix,
+    /// the opcode and the immediate operand in little endian. Only the low
+    /// byte of `immediate` is used by instructions with an 8 bit operand and
+    /// it is ignored by instructions without one.
+    ///
+    /// Returns `None` for LD [HL], [HL], whose opcode 0x76 is HALT.
+    pub fn encode(&self, immediate: u16) -> Option<Vec<u8>> {
+        if *self == Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::HLI)) {
+            return None;
+        }
+        let mut bytes = match self.prefixed_opcode() {
+            Some(opcode) => vec![0xCB, opcode],
+            None => vec![self.opcode()],
+        };
+        let [low, high] = immediate.to_le_bytes();
+        match self.size() as usize - bytes.len() {
+            1 if *self == Instruction::STOP => bytes.push(0x00),
+            1 => bytes.push(low),
+            2 => bytes.extend([low, high]),
+            _ => {}
+        }
+        Some(bytes)
+    }
+
+    fn prefixed_opcode(&self) -> Option<u8> {
+        let opcode = match self {
+            Instruction::RLC(target) => prefix_target(target),
+            Instruction::RRC(target) => 0x08 | prefix_target(target),
+            Instruction::RL(target) => 0x10 | prefix_target(target),
+            Instruction::RR(target) => 0x18 | prefix_target(target),
+            Instruction::SLA(target) => 0x20 | prefix_target(target),
+            Instruction::SRA(target) => 0x28 | prefix_target(target),
+            Instruction::SWAP(target) => 0x30 | prefix_target(target),
+            Instruction::SRL(target) => 0x38 | prefix_target(target),
+            Instruction::BIT(target, position) => {
+                0x40 | bit_position(position) | prefix_target(target)
+            }
+            Instruction::RES(target, position) => {
+                0x80 | bit_position(position) | prefix_target(target)
+            }
+            Instruction::SET(target, position) => {
+                0xC0 | bit_position(position) | prefix_target(target)
+            }
+            _ => return None,
+        };
+        Some(opcode)
+    }
+
+    fn opcode(&self) -> u8 {
+        match self {
+            Instruction::ADD(target) => arithmetic(0, target),
+            Instruction::ADC(target) => arithmetic(1, target),
+            Instruction::SUB(target) => arithmetic(2, target),
+            Instruction::SBC(target) => arithmetic(3, target),
+            Instruction::AND(target) => arithmetic(4, target),
+            Instruction::XOR(target) => arithmetic(5, target),
+            Instruction::OR(target) => arithmetic(6, target),
+            Instruction::CP(target) => arithmetic(7, target),
+            Instruction::INC(target) => match inc_dec_target(target) {
+                Ok(register) => 0x04 | register << 3,
+                Err(pair) => 0x03 | pair << 4,
+            },
+            Instruction::DEC(target) => match inc_dec_target(target) {
+                Ok(register) => 0x05 | register << 3,
+                Err(pair) => 0x0B | pair << 4,
+            },
+            Instruction::DAA => 0x27,
+            Instruction::CPL => 0x2F,
+
+            Instruction::ADDHL(target) => {
+                let pair = match target {
+                    ADDHLTarget::BC => 0,
+                    ADDHLTarget::DE => 1,
+                    ADDHLTarget::HL => 2,
+                    ADDHLTarget::SP => 3,
+                };
+                0x09 | pair << 4
+            }
+            Instruction::ADDSP => 0xE8,
+
+            Instruction::LD(load_type) => load(load_type),
+
+            Instruction::JP(JumpTest::Always) => 0xC3,
+            Instruction::JP(test) => 0xC2 | condition(test),
+            Instruction::JR(JumpTest::Always) => 0x18,
+            Instruction::JR(test) => 0x20 | condition(test),
+            Instruction::JPI => 0xE9,
+
+            Instruction::PUSH(target) => 0xC5 | stack_target(target),
+            Instruction::POP(target) => 0xC1 | stack_target(target),
+            Instruction::CALL(JumpTest::Always) => 0xCD,
+            Instruction::CALL(test) => 0xC4 | condition(test),
+            Instruction::RET(JumpTest::Always) => 0xC9,
+            Instruction::RET(test) => 0xC0 | condition(test),
+            Instruction::RETI => 0xD9,
+            // the vector is stored in bits 3 to 5
+            Instruction::RST(location) => 0xC7 | location.to_hex() as u8,
+
+            Instruction::CCF => 0x3F,
+            Instruction::SCF => 0x37,
+
+            Instruction::RLCA => 0x07,
+            Instruction::RRCA => 0x0F,
+            Instruction::RLA => 0x17,
+            Instruction::RRA => 0x1F,
+
+            Instruction::HALT => 0x76,
+            Instruction::STOP => 0x10,
+            Instruction::NOP => 0x00,
+            Instruction::DI => 0xF3,
+            Instruction::EI => 0xFB,
+
+            // prefixed instructions
+            _ => 0xCB,
+        }
+    }
+}
+
+// 8 bit registers are numbered B, C, D, E, H, L, (HL), A
+fn arithmetic(operation: u8, target: &ArithmeticTarget) -> u8 {
+    let register = match target {
+        ArithmeticTarget::B => 0,
+        ArithmeticTarget::C => 1,
+        ArithmeticTarget::D => 2,
+        ArithmeticTarget::E => 3,
+        ArithmeticTarget::H => 4,
+        ArithmeticTarget::L => 5,
+        ArithmeticTarget::HLI => 6,
+        ArithmeticTarget::A => 7,
+        // the immediate versions live in the 0xC6-0xFE column
+        ArithmeticTarget::D8 => return 0xC6 | operation << 3,
+    };
+    0x80 | operation << 3 | register
+}
+
+// 8 bit register number, or register pair number for 16 bit targets
+fn inc_dec_target(target: &IncDecTarget) -> Result<u8, u8> {
+    match target {
+        IncDecTarget::B => Ok(0),
+        IncDecTarget::C => Ok(1),
+        IncDecTarget::D => Ok(2),
+        IncDecTarget::E => Ok(3),
+        IncDecTarget::H => Ok(4),
+        IncDecTarget::L => Ok(5),
+        IncDecTarget::HLI => Ok(6),
+        IncDecTarget::A => Ok(7),
+        IncDecTarget::BC => Err(0),
+        IncDecTarget::DE => Err(1),
+        IncDecTarget::HL => Err(2),
+        IncDecTarget::SP => Err(3),
+    }
+}
+
+fn load_byte_target(target: &LoadByteTarget) -> u8 {
+    match target {
+        LoadByteTarget::B => 0,
+        LoadByteTarget::C => 1,
+        LoadByteTarget::D => 2,
+        LoadByteTarget::E => 3,
+        LoadByteTarget::H => 4,
+        LoadByteTarget::L => 5,
+        LoadByteTarget::HLI => 6,
+        LoadByteTarget::A => 7,
+    }
+}
+
+fn load(load_type: &LoadType) -> u8 {
+    match load_type {
+        LoadType::Byte(target, source) => {
+            let source = match source {
+                LoadByteSource::B => 0,
+                LoadByteSource::C => 1,
+                LoadByteSource::D => 2,
+                LoadByteSource::E => 3,
+                LoadByteSource::H => 4,
+                LoadByteSource::L => 5,
+                LoadByteSource::HLI => 6,
+                LoadByteSource::A => 7,
+                LoadByteSource::D8 => return 0x06 | load_byte_target(target) << 3,
+            };
+            0x40 | load_byte_target(target) << 3 | source
+        }
+        LoadType::Word(target) => {
+            let pair = match target {
+                LoadWordTarget::BC => 0,
+                LoadWordTarget::DE => 1,
+                LoadWordTarget::HL => 2,
+                LoadWordTarget::SP => 3,
+            };
+            0x01 | pair << 4
+        }
+        // loading into A sets bit 3, or bit 4 for the 0xE0-0xFF block
+        LoadType::AFromIndirect(
+            indirect @ (Indirect::LastByteIndirect | Indirect::WordIndirect),
+        ) => 0x10 | indirect_from_a(indirect),
+        LoadType::AFromIndirect(indirect) => 0x08 | indirect_from_a(indirect),
+        LoadType::IndirectFromA(indirect) => indirect_from_a(indirect),
+        LoadType::AFromByteAddress => 0xF0,
+        LoadType::ByteAddressFromA => 0xE0,
+        LoadType::SPFromHL => 0xF9,
+        LoadType::HLFromSPN => 0xF8,
+        LoadType::IndirectFromSP => 0x08,
+    }
+}
+
+fn indirect_from_a(indirect: &Indirect) -> u8 {
+    match indirect {
+        Indirect::BCIndirect => 0x02,
+        Indirect::DEIndirect => 0x12,
+        Indirect::HLIndirectPlus => 0x22,
+        Indirect::HLIndirectMinus => 0x32,
+        Indirect::LastByteIndirect => 0xE2,
+        Indirect::WordIndirect => 0xEA,
+    }
+}
+
+fn condition(test: &JumpTest) -> u8 {
+    let condition = match test {
+        JumpTest::NotZero => 0,
+        JumpTest::Zero => 1,
+        JumpTest::NotCarry => 2,
+        JumpTest::Carry => 3,
+        JumpTest::Always => unreachable!("unconditional jumps have their own opcode"),
+    };
+    condition << 3
+}
+
+fn stack_target(target: &StackTarget) -> u8 {
+    let pair = match target {
+        StackTarget::BC => 0,
+        StackTarget::DE => 1,
+        StackTarget::HL => 2,
+        StackTarget::AF => 3,
+    };
+    pair << 4
+}
+
+fn prefix_target(target: &PrefixTarget) -> u8 {
+    match target {
+        PrefixTarget::B => 0,
+        PrefixTarget::C => 1,
+        PrefixTarget::D => 2,
+        PrefixTarget::E => 3,
+        PrefixTarget::H => 4,
+        PrefixTarget::L => 5,
+        PrefixTarget::HLI => 6,
+        PrefixTarget::A => 7,
+    }
+}
+
+fn bit_position(position: &BitPosition) -> u8 {
+    u8::from(*position) << 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_every_opcode() {
+        let mut decoded = 0;
+        for prefixed in [false, true] {
+            for opcode in 0..=0xFFu8 {
+                let instruction = match Instruction::from_byte(opcode, prefixed) {
+                    Some(instruction) => instruction,
+                    None => continue,
+                };
+                let expected = if prefixed {
+                    vec![0xCB, opcode]
+                } else {
+                    vec![opcode]
+                };
+                let bytes = instruction.encode(0).unwrap();
+                assert_eq!(
+                    bytes.len(),
+                    instruction.size() as usize,
+                    "{:?}",
+                    instruction
+                );
+                assert_eq!(&bytes[..expected.len()], &expected[..], "{:?}", instruction);
+                decoded += 1;
+            }
+        }
+        // 11 unprefixed opcodes are illegal and 0xCB is the prefix
+        assert_eq!(decoded, 256 - 12 + 256);
+
+        // 0x76 decodes as HALT, so LD [HL], [HL] has no encoding
+        let load = Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::HLI));
+        assert_eq!(load.encode(0), None);
+    }
+
+    #[test]
+    fn encode_immediates_in_little_endian() {
+        let jump = Instruction::JP(JumpTest::Always);
+        assert_eq!(jump.encode(0x0150), Some(vec![0xC3, 0x50, 0x01]));
+        let load = Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::D8));
+        assert_eq!(load.encode(0x42), Some(vec![0x3E, 0x42]));
+        assert_eq!(Instruction::STOP.encode(0x42), Some(vec![0x10, 0x00]));
+        assert_eq!(
+            Instruction::SET(PrefixTarget::HLI, BitPosition::B7).encode(0x42),
+            Some(vec![0xCB, 0xFE])
+        );
+    }
+}
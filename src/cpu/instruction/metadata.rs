@@ -0,0 +1,430 @@
+use super::{ArithmeticTarget, IncDecTarget, Indirect, Instruction, JumpTest, LoadType};
+use super::{LoadByteSource, LoadByteTarget, PrefixTarget, StackTarget};
+
+/// How an instruction changes one of the flags
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FlagEffect {
+    Unaffected,
+    Set,
+    Reset,
+    Computed,
+}
+
+/// Effect of an instruction on each flag of the F register
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlagEffects {
+    pub zero: FlagEffect,
+    pub subtract: FlagEffect,
+    pub half_carry: FlagEffect,
+    pub carry: FlagEffect,
+}
+
+impl FlagEffects {
+    const fn new(
+        zero: FlagEffect,
+        subtract: FlagEffect,
+        half_carry: FlagEffect,
+        carry: FlagEffect,
+    ) -> FlagEffects {
+        FlagEffects {
+            zero,
+            subtract,
+            half_carry,
+            carry,
+        }
+    }
+}
+
+use FlagEffect::{Computed, Reset, Set, Unaffected};
+
+const NO_FLAGS: FlagEffects = FlagEffects::new(Unaffected, Unaffected, Unaffected, Unaffected);
+// Z 0 0 C, used by every rotate and shift except SWAP
+const ROTATE_FLAGS: FlagEffects = FlagEffects::new(Computed, Reset, Reset, Computed);
+
+impl Instruction {
+    /// Length of the encoded instruction in bytes, including the 0xCB prefix
+    /// and the immediate operands.
+    pub const fn size(&self) -> u16 {
+        match self {
+            Instruction::ADD(target)
+            | Instruction::ADC(target)
+            | Instruction::SUB(target)
+            | Instruction::SBC(target)
+            | Instruction::AND(target)
+            | Instruction::XOR(target)
+            | Instruction::OR(target)
+            | Instruction::CP(target) => match target {
+                ArithmeticTarget::D8 => 2,
+                _ => 1,
+            },
+            Instruction::ADDSP => 2,
+
+            Instruction::LD(load_type) => match load_type {
+                LoadType::Byte(_, LoadByteSource::D8) => 2,
+                LoadType::Byte(_, _) => 1,
+                LoadType::Word(_) => 3,
+                LoadType::AFromIndirect(Indirect::WordIndirect)
+                | LoadType::IndirectFromA(Indirect::WordIndirect) => 3,
+                LoadType::AFromIndirect(_) | LoadType::IndirectFromA(_) => 1,
+                LoadType::AFromByteAddress | LoadType::ByteAddressFromA => 2,
+                LoadType::SPFromHL => 1,
+                LoadType::HLFromSPN => 2,
+                LoadType::IndirectFromSP => 3,
+            },
+
+            Instruction::JP(_) | Instruction::CALL(_) => 3,
+            Instruction::JR(_) => 2,
+
+            Instruction::BIT(_, _)
+            | Instruction::RES(_, _)
+            | Instruction::SET(_, _)
+            | Instruction::SRL(_)
+            | Instruction::RR(_)
+            | Instruction::RL(_)
+            | Instruction::RRC(_)
+            | Instruction::RLC(_)
+            | Instruction::SRA(_)
+            | Instruction::SLA(_)
+            | Instruction::SWAP(_) => 2,
+
+            // STOP is followed by a byte that is ignored
+            Instruction::STOP => 2,
+
+            _ => 1,
+        }
+    }
+
+    /// T-cycles taken by the instruction. For conditional jumps, calls and
+    /// returns this is the timing when the condition is not met.
+    pub const fn cycles(&self) -> u8 {
+        match self {
+            Instruction::ADD(target)
+            | Instruction::ADC(target)
+            | Instruction::SUB(target)
+            | Instruction::SBC(target)
+            | Instruction::AND(target)
+            | Instruction::XOR(target)
+            | Instruction::OR(target)
+            | Instruction::CP(target) => match target {
+                ArithmeticTarget::HLI | ArithmeticTarget::D8 => 8,
+                _ => 4,
+            },
+            Instruction::INC(target) | Instruction::DEC(target) => match target {
+                IncDecTarget::HLI => 12,
+                IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => 8,
+                _ => 4,
+            },
+            Instruction::ADDHL(_) => 8,
+            Instruction::ADDSP => 16,
+
+            Instruction::LD(load_type) => match load_type {
+                LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::D8) => 12,
+                LoadType::Byte(LoadByteTarget::HLI, _) => 8,
+                LoadType::Byte(_, LoadByteSource::HLI | LoadByteSource::D8) => 8,
+                LoadType::Byte(_, _) => 4,
+                LoadType::Word(_) => 12,
+                LoadType::AFromIndirect(Indirect::WordIndirect)
+                | LoadType::IndirectFromA(Indirect::WordIndirect) => 16,
+                LoadType::AFromIndirect(_) | LoadType::IndirectFromA(_) => 8,
+                LoadType::AFromByteAddress | LoadType::ByteAddressFromA => 12,
+                LoadType::SPFromHL => 8,
+                LoadType::HLFromSPN => 12,
+                LoadType::IndirectFromSP => 20,
+            },
+
+            Instruction::JP(JumpTest::Always) => 16,
+            Instruction::JP(_) => 12,
+            Instruction::JR(JumpTest::Always) => 12,
+            Instruction::JR(_) => 8,
+            Instruction::JPI => 4,
+            Instruction::PUSH(_) => 16,
+            Instruction::POP(_) => 12,
+            Instruction::CALL(JumpTest::Always) => 24,
+            Instruction::CALL(_) => 12,
+            Instruction::RET(JumpTest::Always) => 16,
+            Instruction::RET(_) => 8,
+            Instruction::RETI => 16,
+            Instruction::RST(_) => 16,
+
+            Instruction::BIT(PrefixTarget::HLI, _) => 12,
+            Instruction::RES(target, _) | Instruction::SET(target, _) => match target {
+                PrefixTarget::HLI => 16,
+                _ => 8,
+            },
+            Instruction::BIT(_, _) => 8,
+            Instruction::SRL(target)
+            | Instruction::RR(target)
+            | Instruction::RL(target)
+            | Instruction::RRC(target)
+            | Instruction::RLC(target)
+            | Instruction::SRA(target)
+            | Instruction::SLA(target)
+            | Instruction::SWAP(target) => match target {
+                PrefixTarget::HLI => 16,
+                _ => 8,
+            },
+
+            _ => 4,
+        }
+    }
+
+    /// T-cycles taken by a conditional jump, call or return when the
+    /// condition is met, `None` for every other instruction.
+    pub const fn branch_cycles(&self) -> Option<u8> {
+        match self {
+            Instruction::JP(JumpTest::Always)
+            | Instruction::JR(JumpTest::Always)
+            | Instruction::CALL(JumpTest::Always)
+            | Instruction::RET(JumpTest::Always) => None,
+            Instruction::JP(_) => Some(16),
+            Instruction::JR(_) => Some(12),
+            Instruction::CALL(_) => Some(24),
+            Instruction::RET(_) => Some(20),
+            _ => None,
+        }
+    }
+
+    /// How the instruction changes each flag of the F register
+    pub fn flag_effects(&self) -> FlagEffects {
+        match self {
+            Instruction::ADD(_) | Instruction::ADC(_) => {
+                FlagEffects::new(Computed, Reset, Computed, Computed)
+            }
+            Instruction::SUB(_) | Instruction::SBC(_) | Instruction::CP(_) => {
+                FlagEffects::new(Computed, Set, Computed, Computed)
+            }
+            Instruction::AND(_) => FlagEffects::new(Computed, Reset, Set, Reset),
+            Instruction::XOR(_) | Instruction::OR(_) => {
+                FlagEffects::new(Computed, Reset, Reset, Reset)
+            }
+            Instruction::INC(target) | Instruction::DEC(target) => match target {
+                IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => {
+                    NO_FLAGS
+                }
+                _ => FlagEffects::new(
+                    Computed,
+                    if matches!(self, Instruction::INC(_)) {
+                        Reset
+                    } else {
+                        Set
+                    },
+                    Computed,
+                    Unaffected,
+                ),
+            },
+            Instruction::DAA => FlagEffects::new(Computed, Unaffected, Reset, Computed),
+            Instruction::CPL => FlagEffects::new(Unaffected, Set, Set, Unaffected),
+            Instruction::ADDHL(_) => FlagEffects::new(Unaffected, Reset, Computed, Computed),
+            Instruction::ADDSP | Instruction::LD(LoadType::HLFromSPN) => {
+                FlagEffects::new(Reset, Reset, Computed, Computed)
+            }
+            Instruction::POP(StackTarget::AF) => {
+                FlagEffects::new(Computed, Computed, Computed, Computed)
+            }
+            Instruction::CCF => FlagEffects::new(Unaffected, Reset, Reset, Computed),
+            Instruction::SCF => FlagEffects::new(Unaffected, Reset, Reset, Set),
+            Instruction::RRA | Instruction::RLA | Instruction::RRCA | Instruction::RLCA => {
+                FlagEffects::new(Reset, Reset, Reset, Computed)
+            }
+            Instruction::BIT(_, _) => FlagEffects::new(Computed, Reset, Set, Unaffected),
+            Instruction::SRL(_)
+            | Instruction::RR(_)
+            | Instruction::RL(_)
+            | Instruction::RRC(_)
+            | Instruction::RLC(_)
+            | Instruction::SRA(_)
+            | Instruction::SLA(_) => ROTATE_FLAGS,
+            Instruction::SWAP(_) => FlagEffects::new(Computed, Reset, Reset, Reset),
+            _ => NO_FLAGS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::flags_register::FlagsRegister;
+    use crate::cpu::CPU;
+
+    // Reference timings from the opcode tables in Pan Docs, kept independent
+    // from the metadata above. 0 marks illegal opcodes and the 0xCB prefix.
+    #[rustfmt::skip]
+    const SIZES: [u16; 256] = [
+        1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 0, 3, 3, 2, 1,
+        1, 1, 3, 0, 3, 1, 2, 1, 1, 1, 3, 0, 3, 0, 2, 1,
+        2, 1, 1, 0, 0, 1, 2, 1, 2, 1, 3, 0, 0, 0, 2, 1,
+        2, 1, 1, 1, 0, 1, 2, 1, 2, 1, 3, 1, 0, 0, 2, 1,
+    ];
+
+    #[rustfmt::skip]
+    const CYCLES: [u8; 256] = [
+         4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4,
+         4, 12,  8,  8,  4,  4,  8,  4, 12,  8,  8,  8,  4,  4,  8,  4,
+         8, 12,  8,  8,  4,  4,  8,  4,  8,  8,  8,  8,  4,  4,  8,  4,
+         8, 12,  8,  8, 12, 12, 12,  4,  8,  8,  8,  8,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         8,  8,  8,  8,  8,  8,  4,  8,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+         8, 12, 12, 16, 12, 16,  8, 16,  8, 16, 12,  0, 12, 24,  8, 16,
+         8, 12, 12,  0, 12, 16,  8, 16,  8, 16, 12,  0, 12,  0,  8, 16,
+        12, 12,  8,  0,  0, 16,  8, 16, 16,  4, 16,  0,  0,  0,  8, 16,
+        12, 12,  8,  4,  0, 16,  8, 16, 12,  8, 16,  4,  0,  0,  8, 16,
+    ];
+
+    // cycles taken by conditional instructions when the condition is met
+    fn reference_branch_cycles(opcode: u8) -> Option<u8> {
+        match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 => Some(12),
+            0xC2 | 0xCA | 0xD2 | 0xDA => Some(16),
+            0xC4 | 0xCC | 0xD4 | 0xDC => Some(24),
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => Some(20),
+            _ => None,
+        }
+    }
+
+    fn reference_prefixed_cycles(opcode: u8) -> u8 {
+        match (opcode & 0x07, opcode >> 6) {
+            (6, 1) => 12, // BIT n, (HL)
+            (6, _) => 16,
+            _ => 8,
+        }
+    }
+
+    fn all_instructions() -> impl Iterator<Item = (u8, bool, Instruction)> {
+        [false, true].into_iter().flat_map(|prefixed| {
+            (0..=0xFFu8).filter_map(move |opcode| {
+                Instruction::from_byte(opcode, prefixed)
+                    .map(|instruction| (opcode, prefixed, instruction))
+            })
+        })
+    }
+
+    fn cpu_for(opcode: u8, prefixed: bool, flags: u8) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.pc = 0xC000;
+        cpu.sp = 0xD000;
+        cpu.registers.set_hl(0xC800);
+        cpu.registers.a = 0x5A;
+        cpu.registers.f = FlagsRegister::from(flags);
+        if prefixed {
+            cpu.bus.write_byte(0xC000, 0xCB);
+            cpu.bus.write_byte(0xC001, opcode);
+        } else {
+            cpu.bus.write_byte(0xC000, opcode);
+            // immediate operands, also used as a non zero relative jump
+            cpu.bus.write_byte(0xC001, 0x10);
+            cpu.bus.write_byte(0xC002, 0x10);
+        }
+        // return address for RET and RETI
+        cpu.bus.write_byte(0xD000, 0x34);
+        cpu.bus.write_byte(0xD001, 0x12);
+        cpu
+    }
+
+    #[test]
+    fn metadata_matches_reference_tables() {
+        for (opcode, prefixed, instruction) in all_instructions() {
+            if prefixed {
+                assert_eq!(instruction.size(), 2, "CB {:02X}", opcode);
+                assert_eq!(
+                    instruction.cycles(),
+                    reference_prefixed_cycles(opcode),
+                    "CB {:02X}",
+                    opcode
+                );
+                assert_eq!(instruction.branch_cycles(), None);
+            } else {
+                let index = opcode as usize;
+                assert_eq!(instruction.size(), SIZES[index], "{:02X}", opcode);
+                assert_eq!(instruction.cycles(), CYCLES[index], "{:02X}", opcode);
+                assert_eq!(
+                    instruction.branch_cycles(),
+                    reference_branch_cycles(opcode),
+                    "{:02X}",
+                    opcode
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_legal_opcode_is_decoded() {
+        let decoded = all_instructions().filter(|(_, prefixed, _)| !prefixed);
+        let expected = SIZES.iter().filter(|&&size| size != 0).count();
+        assert_eq!(decoded.count(), expected);
+        let prefixed = all_instructions().filter(|(_, prefixed, _)| *prefixed);
+        assert_eq!(prefixed.count(), 256);
+    }
+
+    #[test]
+    fn executor_follows_metadata() {
+        for (opcode, prefixed, instruction) in all_instructions() {
+            for flags in [0x00, 0xF0, 0x80, 0x10] {
+                let mut cpu = cpu_for(opcode, prefixed, flags);
+                let before = cpu.registers.f;
+                let (next_pc, cycles) = cpu.execute(instruction);
+
+                let fallthrough = 0xC000 + instruction.size();
+                if next_pc == fallthrough {
+                    assert_eq!(cycles, instruction.cycles(), "{:?}", instruction);
+                } else {
+                    let expected = instruction
+                        .branch_cycles()
+                        .unwrap_or_else(|| instruction.cycles());
+                    assert_eq!(cycles, expected, "{:?}", instruction);
+                }
+
+                let effects = instruction.flag_effects();
+                let after = cpu.registers.f;
+                for (effect, before, after) in [
+                    (effects.zero, before.zero, after.zero),
+                    (effects.subtract, before.subtract, after.subtract),
+                    (effects.half_carry, before.half_carry, after.half_carry),
+                    (effects.carry, before.carry, after.carry),
+                ] {
+                    match effect {
+                        FlagEffect::Unaffected => assert_eq!(before, after, "{:?}", instruction),
+                        FlagEffect::Set => assert!(after, "{:?}", instruction),
+                        FlagEffect::Reset => assert!(!after, "{:?}", instruction),
+                        FlagEffect::Computed => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conditional_jumps_report_taken_and_not_taken_cycles() {
+        // JR NZ with Z set falls through, with Z reset it jumps
+        let mut cpu = cpu_for(0x20, false, 0x80);
+        assert_eq!(cpu.execute(Instruction::JR(JumpTest::NotZero)), (0xC002, 8));
+        let mut cpu = cpu_for(0x20, false, 0x00);
+        assert_eq!(
+            cpu.execute(Instruction::JR(JumpTest::NotZero)),
+            (0xC012, 12)
+        );
+    }
+
+    #[test]
+    fn nop_moves_to_next_byte() {
+        let mut cpu = cpu_for(0x00, false, 0x00);
+        assert_eq!(cpu.execute(Instruction::NOP), (0xC001, 4));
+    }
+}
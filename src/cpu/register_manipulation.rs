@@ -7,25 +7,22 @@
 
 use super::instruction::Instruction;
 use super::instruction::{BitPosition, PrefixTarget};
+use crate::bus::Bus;
 use crate::cpu::CPU;
 
-pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
+pub fn execute<B: Bus>(cpu: &mut CPU<B>, instruction: Instruction) {
     match instruction {
         Instruction::RRA => {
             cpu.registers.a = rotate_right_through_carry(cpu, cpu.registers.a, false);
-            (cpu.pc.wrapping_add(1), 4)
         }
         Instruction::RLA => {
             cpu.registers.a = rotate_left_through_carry(cpu, cpu.registers.a, false);
-            (cpu.pc.wrapping_add(1), 4)
         }
         Instruction::RRCA => {
             cpu.registers.a = rotate_right(cpu, cpu.registers.a, false);
-            (cpu.pc.wrapping_add(1), 4)
         }
         Instruction::RLCA => {
             cpu.registers.a = rotate_left(cpu, cpu.registers.a, false);
-            (cpu.pc.wrapping_add(1), 4)
         }
 
         Instruction::BIT(prefix, bit_position) => {
@@ -39,15 +36,10 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l,
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    cpu.bus.read_byte(address)
+                    cpu.read_byte(address)
                 }
             };
             bit_test(cpu, register, bit_position);
-
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::RES(prefix, bit_position) => {
@@ -61,15 +53,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = reset_bit(cpu.registers.l, bit_position),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = reset_bit(value, bit_position);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::SET(prefix, bit_position) => {
@@ -83,15 +71,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = set_bit(cpu.registers.l, bit_position),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = set_bit(value, bit_position);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::SRL(prefix) => {
@@ -105,15 +89,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = shift_right_logical(cpu, cpu.registers.l),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = shift_right_logical(cpu, value);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::SLA(prefix) => {
@@ -127,15 +107,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = shift_left_arithmetic(cpu, cpu.registers.l),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = shift_left_arithmetic(cpu, value);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::SRA(prefix) => {
@@ -149,15 +125,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = shift_right_arithmetic(cpu, cpu.registers.l),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = shift_right_arithmetic(cpu, value);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::RR(prefix) => {
@@ -192,15 +164,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
 
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = rotate_right_through_carry(cpu, value, true);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::RL(prefix) => {
@@ -228,15 +196,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 }
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = rotate_left_through_carry(cpu, value, true);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::RRC(prefix) => {
@@ -250,15 +214,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = rotate_right(cpu, cpu.registers.l, true),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = rotate_right(cpu, value, true);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::RLC(prefix) => {
@@ -272,16 +232,11 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = rotate_left(cpu, cpu.registers.l, true),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = rotate_left(cpu, value, true);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
         Instruction::SWAP(prefix) => {
@@ -295,25 +250,18 @@ pub fn execute(cpu: &mut CPU, instruction: Instruction) -> (u16, u8) {
                 PrefixTarget::L => cpu.registers.l = swap_nibbles(cpu, cpu.registers.l),
                 PrefixTarget::HLI => {
                     let address = cpu.registers.get_hl();
-                    let value = cpu.bus.read_byte(address);
+                    let value = cpu.read_byte(address);
                     let result = swap_nibbles(cpu, value);
-                    cpu.bus.write_byte(address, result);
+                    cpu.write_byte(address, result);
                 }
             };
-            match prefix {
-                PrefixTarget::HLI => (cpu.pc.wrapping_add(2), 16),
-                _ => (cpu.pc.wrapping_add(2), 8),
-            }
         }
 
-        _ => {
-            /*ignore other instructions*/
-            (0, 0)
-        }
+        _ => { /*ignore other instructions*/ }
     }
 }
 
-fn rotate_right_through_carry(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
+fn rotate_right_through_carry<B: Bus>(cpu: &mut CPU<B>, value: u8, set_zero: bool) -> u8 {
     let carry_bit = if cpu.registers.f.carry { 1 } else { 0 } << 7;
     let new_value = carry_bit | (value >> 1);
     cpu.registers.f.zero = set_zero && new_value == 0;
@@ -323,7 +271,7 @@ fn rotate_right_through_carry(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
     new_value
 }
 
-fn rotate_left_through_carry(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
+fn rotate_left_through_carry<B: Bus>(cpu: &mut CPU<B>, value: u8, set_zero: bool) -> u8 {
     let carry_bit = if cpu.registers.f.carry { 1 } else { 0 };
     let new_value = (value << 1) | carry_bit;
     cpu.registers.f.zero = set_zero && new_value == 0;
@@ -333,7 +281,7 @@ fn rotate_left_through_carry(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
     new_value
 }
 
-fn rotate_left(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
+fn rotate_left<B: Bus>(cpu: &mut CPU<B>, value: u8, set_zero: bool) -> u8 {
     let carry = (value & 0x80) >> 7;
     let new_value = value.rotate_left(1) | carry;
     cpu.registers.f.zero = set_zero && new_value == 0;
@@ -343,7 +291,7 @@ fn rotate_left(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
     new_value
 }
 
-fn rotate_right(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
+fn rotate_right<B: Bus>(cpu: &mut CPU<B>, value: u8, set_zero: bool) -> u8 {
     let new_value = value.rotate_right(1);
     cpu.registers.f.zero = set_zero && new_value == 0;
     cpu.registers.f.subtract = false;
@@ -352,7 +300,7 @@ fn rotate_right(cpu: &mut CPU, value: u8, set_zero: bool) -> u8 {
     new_value
 }
 
-fn bit_test(cpu: &mut CPU, value: u8, bit_position: BitPosition) {
+fn bit_test<B: Bus>(cpu: &mut CPU<B>, value: u8, bit_position: BitPosition) {
     let bit_position: u8 = bit_position.into();
     let result = (value >> bit_position) & 0b1;
     cpu.registers.f.zero = result == 0;
@@ -370,7 +318,7 @@ fn set_bit(value: u8, bit_position: BitPosition) -> u8 {
     value | (1 << bit_position)
 }
 
-fn shift_right_logical(cpu: &mut CPU, value: u8) -> u8 {
+fn shift_right_logical<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let new_value = value >> 1;
     cpu.registers.f.zero = new_value == 0;
     cpu.registers.f.subtract = false;
@@ -379,7 +327,7 @@ fn shift_right_logical(cpu: &mut CPU, value: u8) -> u8 {
     new_value
 }
 
-fn shift_right_arithmetic(cpu: &mut CPU, value: u8) -> u8 {
+fn shift_right_arithmetic<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let msb = value & 0x80;
     let new_value = msb | (value >> 1);
     cpu.registers.f.zero = new_value == 0;
@@ -389,7 +337,7 @@ fn shift_right_arithmetic(cpu: &mut CPU, value: u8) -> u8 {
     new_value
 }
 
-fn shift_left_arithmetic(cpu: &mut CPU, value: u8) -> u8 {
+fn shift_left_arithmetic<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let new_value = value << 1;
     cpu.registers.f.zero = new_value == 0;
     cpu.registers.f.subtract = false;
@@ -398,7 +346,7 @@ fn shift_left_arithmetic(cpu: &mut CPU, value: u8) -> u8 {
     new_value
 }
 
-fn swap_nibbles(cpu: &mut CPU, value: u8) -> u8 {
+fn swap_nibbles<B: Bus>(cpu: &mut CPU<B>, value: u8) -> u8 {
     let new_value = ((value & 0xf) << 4) | ((value & 0xf0) >> 4);
     cpu.registers.f.zero = new_value == 0;
     cpu.registers.f.subtract = false;
@@ -0,0 +1,168 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use super::CPU;
+use crate::bus::Bus;
+
+/// Writes the CPU state before every executed instruction in the format used
+/// by Gameboy Doctor, so traces can be diffed against other emulators:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// Interrupt dispatches and halted steps don't execute an instruction and
+/// aren't traced.
+pub struct Tracer {
+    output: Box<dyn Write>,
+    pub pc_range: RangeInclusive<u16>, //only instructions at these addresses are traced
+    pub start: u64,                    //number of instructions executed before tracing starts
+    pub limit: Option<u64>,            //maximum number of lines written
+    pub instructions: u64,             //instructions seen since the tracer was attached
+    pub lines: u64,                    //lines written so far
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(output: impl Write + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            pc_range: 0x0000..=0xFFFF,
+            start: 0,
+            limit: None,
+            instructions: 0,
+            lines: 0,
+            error: None,
+        }
+    }
+
+    /// Formats the state of `cpu` as a single trace line, without the
+    /// trailing newline. PCMEM is read straight from the bus, so it doesn't
+    /// take any cycles or show up in the access log.
+    pub fn format_state<B: Bus>(cpu: &CPU<B>) -> String {
+        let registers = &cpu.registers;
+        let pc_mem: Vec<String> = (0..4)
+            .map(|offset| format!("{:02X}", cpu.bus.peek(cpu.pc.wrapping_add(offset))))
+            .collect();
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+            registers.a,
+            u8::from(registers.f),
+            registers.b,
+            registers.c,
+            registers.d,
+            registers.e,
+            registers.h,
+            registers.l,
+            cpu.sp,
+            cpu.pc,
+            pc_mem.join(",")
+        )
+    }
+
+    /// Called by the CPU right before it fetches an instruction
+    pub(super) fn trace<B: Bus>(&mut self, cpu: &CPU<B>) {
+        let index = self.instructions;
+        self.instructions += 1;
+        if index < self.start
+            || !self.pc_range.contains(&cpu.pc)
+            || self.limit.is_some_and(|limit| self.lines >= limit)
+            || self.error.is_some()
+        {
+            return;
+        }
+        // the first write error stops the trace, it's reported by `finish`
+        if let Err(error) = writeln!(self.output, "{}", Self::format_state(cpu)) {
+            self.error = Some(error);
+            return;
+        }
+        self.lines += 1;
+    }
+
+    /// Flushes the output and returns the first error hit while tracing
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.output.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    // 0x100: NOP; 0x101: JP 0x0150; 0x150: NOP...
+    fn cpu_with_entry_point() -> CPU {
+        let mut cpu = CPU::with_model(Model::DMG);
+        cpu.bus.cartridge.rom_mut()[0x101] = 0xC3;
+        cpu.bus.cartridge.rom_mut()[0x102] = 0x50;
+        cpu.bus.cartridge.rom_mut()[0x103] = 0x01;
+        cpu
+    }
+
+    #[test]
+    fn traces_state_before_each_instruction() {
+        let mut cpu = cpu_with_entry_point();
+        let buffer = SharedBuffer::default();
+        cpu.tracer = Some(Tracer::new(buffer.clone()));
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+                "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00",
+                "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:00,00,00,00",
+            ]
+        );
+        assert!(cpu.tracer.take().unwrap().finish().is_ok());
+    }
+
+    #[test]
+    fn filters_by_pc_range_and_instruction_count() {
+        let mut cpu = cpu_with_entry_point();
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(buffer.clone());
+        tracer.pc_range = 0x0101..=0x0200;
+        tracer.start = 1;
+        tracer.limit = Some(2);
+        cpu.tracer = Some(tracer);
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        let pcs: Vec<String> = buffer
+            .lines()
+            .iter()
+            .map(|line| line[line.find("PC:").unwrap()..][..7].to_string())
+            .collect();
+        assert_eq!(pcs, vec!["PC:0101", "PC:0150"]);
+        assert_eq!(cpu.tracer.as_ref().unwrap().instructions, 5);
+    }
+}
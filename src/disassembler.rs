@@ -0,0 +1,123 @@
+use std::fmt;
+
+use crate::bus::Bus;
+use crate::cpu::instruction::Instruction;
+
+/// One decoded instruction of a disassembled memory range
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisassembledInstruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,   // opcode (with the 0xCB prefix) followed by its operands
+    pub mnemonic: String, // RGBDS syntax, illegal opcodes are shown as `DB $xx`
+}
+
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {}",
+            self.address,
+            bytes.join(" "),
+            self.mnemonic
+        )
+    }
+}
+
+/// Decodes the instructions stored between `start` and `end` (inclusive).
+///
+/// The last instruction is decoded completely even if its operands are past
+/// `end`. Memory is read with `Bus::peek`, so disassembling has no side
+/// effects on the emulation.
+pub fn disassemble(bus: &impl Bus, start: u16, end: u16) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::new();
+    // a u32 lets a range ending at 0xFFFF terminate
+    let mut address = start as u32;
+    while address <= end as u32 {
+        let instruction = disassemble_instruction(bus, address as u16);
+        address += instruction.bytes.len() as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Decodes the single instruction stored at `address`
+pub fn disassemble_instruction(bus: &impl Bus, address: u16) -> DisassembledInstruction {
+    let opcode = bus.peek(address);
+    let (instruction, opcode_size) = if opcode == 0xCB {
+        let opcode = bus.peek(address.wrapping_add(1));
+        (Instruction::from_byte(opcode, true), 2)
+    } else {
+        (Instruction::from_byte(opcode, false), 1)
+    };
+
+    match instruction {
+        Some(instruction) => {
+            let bytes: Vec<u8> = (0..instruction.size())
+                .map(|offset| bus.peek(address.wrapping_add(offset)))
+                .collect();
+            let mnemonic = instruction
+                .with_operands(address, &bytes[opcode_size..])
+                .to_string();
+            DisassembledInstruction {
+                address,
+                bytes,
+                mnemonic,
+            }
+        }
+        None => DisassembledInstruction {
+            address,
+            bytes: vec![opcode],
+            mnemonic: format!("DB ${:02X}", opcode),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_bus::MemoryBus;
+
+    #[test]
+    fn disassemble_range() {
+        let mut bus = MemoryBus::new();
+        let program = [0x31, 0xFE, 0xFF, 0xAF, 0xCB, 0x7C, 0x20, 0xFB, 0xE0, 0x50];
+        bus.cartridge.rom_mut()[0x100..0x100 + program.len()].copy_from_slice(&program);
+
+        let instructions = disassemble(&bus, 0x100, 0x109);
+        let listing: Vec<(u16, &[u8], &str)> = instructions
+            .iter()
+            .map(|i| (i.address, i.bytes.as_slice(), i.mnemonic.as_str()))
+            .collect();
+        assert_eq!(
+            listing,
+            vec![
+                (0x100, &[0x31, 0xFE, 0xFF][..], "LD SP, $FFFE"),
+                (0x103, &[0xAF][..], "XOR A, A"),
+                (0x104, &[0xCB, 0x7C][..], "BIT 7, H"),
+                (0x106, &[0x20, 0xFB][..], "JR NZ, $0103"),
+                (0x108, &[0xE0, 0x50][..], "LDH [$FF50], A"),
+            ]
+        );
+    }
+
+    #[test]
+    fn illegal_opcodes_are_shown_as_data() {
+        let mut bus = MemoryBus::new();
+        bus.cartridge.rom_mut()[0x200] = 0xDD;
+        let instruction = disassemble_instruction(&bus, 0x200);
+        assert_eq!(instruction.bytes, vec![0xDD]);
+        assert_eq!(instruction.to_string(), "0200  DD        DB $DD");
+    }
+
+    #[test]
+    fn range_can_end_at_the_top_of_memory() {
+        let bus = MemoryBus::new();
+        let instructions = disassemble(&bus, 0xFFF0, 0xFFFE);
+        assert_eq!(instructions.len(), 15);
+    }
+}
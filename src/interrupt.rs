@@ -0,0 +1,76 @@
+/// Interrupt sources, declared from the highest to the lowest priority.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+pub const INTERRUPT_ENABLE_ADDRESS: u16 = 0xFFFF;
+pub const INTERRUPT_FLAG_ADDRESS: u16 = 0xFF0F;
+
+impl Interrupt {
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// Bit of this interrupt in the IE and IF registers
+    pub fn mask(&self) -> u8 {
+        match self {
+            Interrupt::VBlank => 1 << 0,
+            Interrupt::LcdStat => 1 << 1,
+            Interrupt::Timer => 1 << 2,
+            Interrupt::Serial => 1 << 3,
+            Interrupt::Joypad => 1 << 4,
+        }
+    }
+
+    /// Address the CPU jumps to when servicing this interrupt
+    pub fn vector(&self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::LcdStat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
+
+    /// Returns the highest priority interrupt whose bit is set in `bits`
+    pub fn highest_priority(bits: u8) -> Option<Interrupt> {
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| bits & interrupt.mask() != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn vblank_has_highest_priority() {
+        assert_eq!(
+            Interrupt::highest_priority(0b1_1111),
+            Some(Interrupt::VBlank)
+        );
+        assert_eq!(
+            Interrupt::highest_priority(0b1_0100),
+            Some(Interrupt::Timer)
+        );
+        assert_eq!(
+            Interrupt::highest_priority(0b1_0000),
+            Some(Interrupt::Joypad)
+        );
+    }
+
+    #[test]
+    fn unused_bits_are_ignored() {
+        assert_eq!(Interrupt::highest_priority(0b1110_0000), None);
+    }
+}
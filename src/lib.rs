@@ -1,7 +1,89 @@
+pub mod assembler;
+pub mod bus;
+pub mod cartridge;
 pub mod cpu;
+pub mod disassembler;
+pub mod interrupt;
+pub mod mapper;
 pub mod memory_bus;
-use cpu::CPU;
+pub mod model;
+pub mod test_roms;
+use bus::Bus;
+use cpu::{StepError, CPU};
 
-pub fn run(cpu: &mut CPU) {
-    todo!("implement clock logic");
+/// Number of T-cycles the DMG takes to draw one full frame (154 lines of 456 dots).
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+/// Number of T-cycles the CPU runs per second at normal speed.
+pub const CYCLES_PER_SECOND: u64 = 4_194_304;
+
+/// Runs the emulation until the CPU hits an error
+pub fn run<B: Bus>(cpu: &mut CPU<B>) -> Result<(), StepError> {
+    loop {
+        run_frame(cpu)?;
+    }
+}
+
+/// Runs the CPU until the current frame is complete and returns the number
+/// of T-cycles executed.
+///
+/// Frame boundaries are multiples of `CYCLES_PER_FRAME` on the CPU cycle
+/// counter, so an instruction that overshoots the end of a frame shortens
+/// the next one instead of drifting.
+pub fn run_frame<B: Bus>(cpu: &mut CPU<B>) -> Result<u64, StepError> {
+    let frame_end = (cpu.cycles / CYCLES_PER_FRAME + 1) * CYCLES_PER_FRAME;
+    run_for_cycles(cpu, frame_end - cpu.cycles)
+}
+
+/// Runs the CPU for at least `cycles` T-cycles and returns the number of
+/// T-cycles actually executed, which can be slightly more since
+/// instructions are never split.
+pub fn run_for_cycles<B: Bus>(cpu: &mut CPU<B>, cycles: u64) -> Result<u64, StepError> {
+    let start = cpu.cycles;
+    while cpu.cycles - start < cycles {
+        cpu.step()?;
+    }
+    Ok(cpu.cycles - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn run_for_cycles_does_not_split_instructions() {
+        let mut cpu = CPU::new();
+        // memory is zeroed, so every instruction is a 4 cycles NOP
+        assert_eq!(run_for_cycles(&mut cpu, 10), Ok(12));
+        assert_eq!(cpu.cycles, 12);
+    }
+
+    #[test]
+    fn run_frame_stops_at_frame_boundary() {
+        let mut cpu = CPU::new();
+        assert_eq!(run_frame(&mut cpu), Ok(CYCLES_PER_FRAME));
+        run_for_cycles(&mut cpu, 6).unwrap();
+        assert_eq!(run_frame(&mut cpu), Ok(CYCLES_PER_FRAME - 8));
+        assert_eq!(cpu.cycles, 2 * CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn run_stops_on_illegal_opcode() {
+        let mut cpu = CPU::new();
+        cpu.bus.cartridge.rom_mut()[0x1] = 0xDD;
+        assert_eq!(
+            run(&mut cpu),
+            Err(StepError::IllegalOpcode {
+                opcode: 0xDD,
+                address: 0x1
+            })
+        );
+    }
+
+    #[test]
+    fn double_speed_runs_twice_as_many_instructions_per_frame() {
+        let mut cpu = CPU::new();
+        cpu.bus.double_speed = true;
+        run_frame(&mut cpu).unwrap();
+        assert_eq!(cpu.bus.div_counter, (2 * CYCLES_PER_FRAME) as u16);
+    }
 }
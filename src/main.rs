@@ -1,9 +1,160 @@
-pub mod cpu;
-pub mod memory_bus;
+use std::path::Path;
 
+use dmg_01::cartridge::Cartridge;
 use dmg_01::cpu::CPU;
+use dmg_01::mapper::{self, TiltScript};
+use dmg_01::model::Model;
+use dmg_01::test_roms::{blargg, mooneye, TestResult};
+
+// emulated time given to a test ROM before it is considered stuck
+const TEST_ROM_TIMEOUT_SECONDS: u64 = 120;
+
+// battery backed RAM is written back to the save file about once a second
+const SAVE_INTERVAL_FRAMES: u64 = 60;
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("blargg") => run_blargg(&args[1..]),
+        Some("mooneye") => run_mooneye(&args[1..]),
+        Some(rom) => run_cartridge(Path::new(rom), args.get(1).map(Path::new)),
+        None => {
+            let mut cpu = CPU::with_model(Model::DMG);
+            if let Err(error) = dmg_01::run(&mut cpu) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// dmg_01 <rom> [tilt script]
+fn run_cartridge(path: &Path, tilt_path: Option<&Path>) {
+    let cartridge = match Cartridge::open(path) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    };
+    let tilt_script = match tilt_path.map(TiltScript::open).transpose() {
+        Ok(script) => script.unwrap_or_default(),
+        Err(error) => {
+            eprintln!("{}: {}", tilt_path.unwrap().display(), error);
+            std::process::exit(1);
+        }
+    };
+    for warning in &cartridge.warnings {
+        eprintln!("{}: warning: {}", path.display(), warning);
+    }
+    let header = &cartridge.header;
+    let model = header.model();
+    println!(
+        "{} ({}, {} KiB ROM, {} KiB RAM)",
+        header.title,
+        header.cartridge_type,
+        header.rom_size / 1024,
+        header.ram_size / 1024
+    );
+
     let mut cpu = CPU::new();
+    cpu.bus.load_cartridge(cartridge);
+    let save_path = mapper::save_path(path);
+    if let Err(error) = mapper::load_save(cpu.bus.cartridge.as_mut(), &save_path) {
+        eprintln!("{}: {}", save_path.display(), error);
+        std::process::exit(1);
+    }
+    cpu.reset_to_post_boot(model);
+
+    let mut saved = cpu.bus.cartridge.save_data();
+    let mut frames: u64 = 0;
+    let mut rumble = false;
+    loop {
+        if let Some((x, y)) = tilt_script.tilt_at(frames) {
+            cpu.bus.cartridge.set_tilt(x, y);
+        }
+        let result = dmg_01::run_frame(&mut cpu);
+        frames += 1;
+        // the motor state is reported once per frame, games pulse it faster
+        if let Some(event) = cpu.bus.cartridge.take_rumble_events().last() {
+            if event.on != rumble {
+                rumble = event.on;
+                println!("rumble {}", if rumble { "on" } else { "off" });
+            }
+        }
+        // the save is also written when the emulation stops on an error
+        if result.is_err() || frames.is_multiple_of(SAVE_INTERVAL_FRAMES) {
+            let data = cpu.bus.cartridge.save_data();
+            if data != saved {
+                if let Err(error) = mapper::write_save(cpu.bus.cartridge.as_ref(), &save_path) {
+                    eprintln!("{}: {}", save_path.display(), error);
+                    std::process::exit(1);
+                }
+                saved = data;
+            }
+        }
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+// dmg_01 blargg <rom>...
+fn run_blargg(roms: &[String]) {
+    if roms.is_empty() {
+        eprintln!("usage: dmg_01 blargg <rom>...");
+        std::process::exit(2);
+    }
+
+    let timeout = TEST_ROM_TIMEOUT_SECONDS * dmg_01::CYCLES_PER_SECOND;
+    let mut all_passed = true;
+    for rom in roms {
+        match blargg::run_rom(Path::new(rom), timeout) {
+            Ok((result, output)) => {
+                println!("{}: {}", rom, result);
+                if result != TestResult::Passed {
+                    all_passed = false;
+                    println!("{}", output.trim_end());
+                }
+            }
+            Err(error) => {
+                all_passed = false;
+                println!("{}: {}", rom, error);
+            }
+        }
+    }
+    if !all_passed {
+        std::process::exit(1);
+    }
+}
+
+// dmg_01 mooneye <mooneye build directory>
+fn run_mooneye(args: &[String]) {
+    let dir = match args {
+        [dir] => Path::new(dir),
+        _ => {
+            eprintln!("usage: dmg_01 mooneye <dir>");
+            std::process::exit(2);
+        }
+    };
 
-    dmg_01::run(&mut cpu);
+    let timeout = TEST_ROM_TIMEOUT_SECONDS * dmg_01::CYCLES_PER_SECOND;
+    let reports = match mooneye::run_directory(dir, timeout) {
+        Ok(reports) => reports,
+        Err(error) => {
+            eprintln!("{}: {}", dir.display(), error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = mooneye::print_matrix(&reports, &mut std::io::stdout()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    if reports
+        .iter()
+        .any(|report| report.passed() != report.results.len())
+    {
+        std::process::exit(1);
+    }
 }
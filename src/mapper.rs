@@ -0,0 +1,236 @@
+mod eeprom;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc7;
+mod rtc;
+mod tilt;
+
+pub use eeprom::EEPROM;
+pub use mbc1::MBC1;
+pub use mbc2::MBC2;
+pub use mbc3::MBC3;
+pub use mbc5::MBC5;
+pub use mbc7::MBC7;
+pub use rtc::{RTCClock, RTC};
+pub use tilt::{TiltScript, TiltScriptError};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Size of the switchable ROM banks of the memory bank controllers
+pub const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Rumble motor of the cartridge turned on or off
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RumbleEvent {
+    pub cycle: u64, //cartridge T-cycles since power on
+    pub on: bool,
+}
+
+/// Cartridge hardware seen by the bus: the ROM at 0x0000-0x7FFF and the
+/// external RAM at 0xA000-0xBFFF. Writes to the ROM area don't change the
+/// ROM, they are commands for the memory bank controller.
+pub trait Mapper {
+    fn read_rom(&self, address: u16) -> u8;
+    fn write_rom(&mut self, address: u16, value: u8);
+    fn read_ram(&self, address: u16) -> u8;
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    /// Raw ROM contents, independent of the selected banks. Used to load
+    /// programs and patch code without going through the bus.
+    fn rom(&self) -> &[u8];
+    fn rom_mut(&mut self) -> &mut [u8];
+
+    /// Advances cartridge hardware with its own clock, like the MBC3 RTC, by
+    /// `cycles` normal speed T-cycles
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// Drains the rumble motor changes since the last call, empty for
+    /// cartridges without a motor
+    fn take_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        Vec::new()
+    }
+
+    /// Sets the acceleration in g seen by cartridges with a tilt sensor
+    fn set_tilt(&mut self, _x: f64, _y: f64) {}
+
+    /// Battery backed memory to keep in the save file, `None` for cartridges
+    /// without a battery
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores the memory returned by `save_data`
+    fn load_save_data(&mut self, _data: &[u8]) {}
+}
+
+/// Save file of the ROM at `rom_path`: the same name with a .sav extension
+pub fn save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+/// Restores the battery backed memory of `mapper` from `path`. A missing
+/// file is not an error, the game simply starts without a save.
+pub fn load_save(mapper: &mut dyn Mapper, path: &Path) -> io::Result<()> {
+    if mapper.save_data().is_none() {
+        return Ok(());
+    }
+    match fs::read(path) {
+        Ok(data) => {
+            mapper.load_save_data(&data);
+            Ok(())
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Writes the battery backed memory of `mapper` to `path`, if it has any
+pub fn write_save(mapper: &dyn Mapper, path: &Path) -> io::Result<()> {
+    match mapper.save_data() {
+        Some(data) => fs::write(path, data),
+        None => Ok(()),
+    }
+}
+
+// Save files of a different size, e.g. from another emulator, are loaded
+// as far as they fit
+fn restore(memory: &mut [u8], data: &[u8]) {
+    let size = memory.len().min(data.len());
+    memory[..size].copy_from_slice(&data[..size]);
+}
+
+/// Size of the ROM area of the address space
+pub const ROM_ONLY_SIZE: usize = 0x8000;
+
+/// Cartridge without a memory bank controller: 32 KiB of ROM and up to
+/// 8 KiB of RAM mapped as is.
+pub struct RomOnly {
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub battery: bool,
+}
+
+impl Default for RomOnly {
+    fn default() -> Self {
+        Self::new(vec![0x0; ROM_ONLY_SIZE])
+    }
+}
+
+impl RomOnly {
+    /// Creates a cartridge without RAM. ROMs shorter than 32 KiB are padded
+    /// with 0xFF, the value read from a floating bus.
+    pub fn new(mut rom: Vec<u8>) -> Self {
+        rom.resize(ROM_ONLY_SIZE, 0xFF);
+        Self {
+            rom,
+            ram: vec![],
+            battery: false,
+        }
+    }
+}
+
+impl Mapper for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom[address as usize]
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        let offset = (address - 0xA000) as usize;
+        self.ram.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        let offset = (address - 0xA000) as usize;
+        if let Some(byte) = self.ram.get_mut(offset) {
+            *byte = value;
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Every bank starts with its number, the low byte then the high byte
+    pub(crate) fn numbered_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0x0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn rom_ignores_writes() {
+        let mut cartridge = RomOnly::new(vec![0x42; 0x100]);
+        cartridge.write_rom(0x0000, 0x00);
+        assert_eq!(cartridge.read_rom(0x0000), 0x42);
+        assert_eq!(cartridge.read_rom(0x7FFF), 0xFF);
+    }
+
+    #[test]
+    fn missing_ram_reads_open_bus() {
+        let mut cartridge = RomOnly::default();
+        cartridge.write_ram(0xA000, 0x12);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+
+        cartridge.ram = vec![0x0; 0x2000];
+        cartridge.write_ram(0xBFFF, 0x12);
+        assert_eq!(cartridge.read_ram(0xBFFF), 0x12);
+    }
+
+    #[test]
+    fn battery_ram_round_trips_through_the_save_file() {
+        let path = std::env::temp_dir().join(format!("dmg_01_save_{}.sav", std::process::id()));
+        let mut cartridge = RomOnly {
+            ram: vec![0x0; 0x2000],
+            ..RomOnly::default()
+        };
+        write_save(&cartridge, &path).unwrap();
+        assert!(!path.exists());
+
+        cartridge.battery = true;
+        cartridge.write_ram(0xA123, 0x42);
+        write_save(&cartridge, &path).unwrap();
+
+        let mut restored = RomOnly {
+            ram: vec![0x0; 0x2000],
+            battery: true,
+            ..RomOnly::default()
+        };
+        load_save(&mut restored, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.read_ram(0xA123), 0x42);
+
+        // a missing save file leaves the RAM as is
+        load_save(&mut restored, &path).unwrap();
+        assert_eq!(restored.read_ram(0xA123), 0x42);
+        assert_eq!(
+            save_path(Path::new("roms/game.gb")),
+            Path::new("roms/game.sav")
+        );
+    }
+}
@@ -0,0 +1,271 @@
+/// Size of the 93LC56 EEPROM: 128 words of 16 bits
+pub const EEPROM_SIZE: usize = 0x100;
+
+const COMMAND_BITS: u8 = 10; //2 bit opcode and 8 bit address after the start bit
+const WORD_BITS: u8 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    // waiting for the start bit
+    Idle,
+    // receiving the opcode and address
+    Command {
+        value: u16,
+        bits: u8,
+    },
+    // sending words, `bits` left in `word`
+    Read {
+        address: u8,
+        word: u16,
+        bits: u8,
+    },
+    // receiving data, WRAL has no address
+    Write {
+        address: Option<u8>,
+        value: u16,
+        bits: u8,
+    },
+    // ignoring bits until CS goes low
+    Done,
+}
+
+/// 93LC56 serial EEPROM in 16 bit mode, driven one bit at a time through
+/// its chip select (CS), clock (CLK) and data in (DI) pins. Bits are
+/// sampled on the rising edge of CLK. Writes complete immediately, so data
+/// out (DO) reports ready as soon as the last bit is received.
+pub struct EEPROM {
+    pub data: [u8; EEPROM_SIZE], //words stored little endian
+    pub write_enabled: bool,     //set by EWEN, cleared by EWDS
+    pub cs: bool,
+    pub clk: bool,
+    pub di: bool,
+    pub data_out: bool,
+    state: State,
+}
+
+impl Default for EEPROM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EEPROM {
+    /// Creates an erased EEPROM, every bit set
+    pub fn new() -> Self {
+        Self {
+            data: [0xFF; EEPROM_SIZE],
+            write_enabled: false,
+            cs: false,
+            clk: false,
+            di: false,
+            data_out: true,
+            state: State::Idle,
+        }
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let offset = (address as usize & 0x7F) * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    fn set_word(&mut self, address: u8, value: u16) {
+        if self.write_enabled {
+            let offset = (address as usize & 0x7F) * 2;
+            self.data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Pin state as seen by the game: CS in bit 7, CLK in bit 6, DI in
+    /// bit 1 and DO in bit 0
+    pub fn read_pins(&self) -> u8 {
+        (self.cs as u8) << 7 | (self.clk as u8) << 6 | (self.di as u8) << 1 | self.data_out as u8
+    }
+
+    /// Drives the pins from a game write, same layout as `read_pins`
+    pub fn write_pins(&mut self, value: u8) {
+        let cs = value & 0x80 != 0;
+        let clk = value & 0x40 != 0;
+        self.di = value & 0x02 != 0;
+        if !cs {
+            // deselecting the chip aborts the command
+            self.state = State::Idle;
+            self.data_out = true;
+        } else if clk && !self.clk {
+            self.clock_bit(self.di);
+        }
+        self.cs = cs;
+        self.clk = clk;
+    }
+
+    fn clock_bit(&mut self, bit: bool) {
+        self.state = match self.state {
+            State::Idle if bit => State::Command { value: 0, bits: 0 },
+            State::Idle => State::Idle,
+            State::Command { value, bits } => {
+                let value = value << 1 | bit as u16;
+                if bits + 1 == COMMAND_BITS {
+                    self.execute(value)
+                } else {
+                    State::Command {
+                        value,
+                        bits: bits + 1,
+                    }
+                }
+            }
+            State::Read {
+                mut address,
+                mut word,
+                mut bits,
+            } => {
+                // sequential reads continue with the next word
+                if bits == 0 {
+                    address = address.wrapping_add(1) & 0x7F;
+                    word = self.word(address);
+                    bits = WORD_BITS;
+                }
+                self.data_out = word & 0x8000 != 0;
+                State::Read {
+                    address,
+                    word: word << 1,
+                    bits: bits - 1,
+                }
+            }
+            State::Write {
+                address,
+                value,
+                bits,
+            } => {
+                let value = value << 1 | bit as u16;
+                if bits + 1 < WORD_BITS {
+                    State::Write {
+                        address,
+                        value,
+                        bits: bits + 1,
+                    }
+                } else {
+                    match address {
+                        Some(address) => self.set_word(address, value),
+                        None => (0..0x80).for_each(|address| self.set_word(address, value)),
+                    }
+                    self.data_out = true;
+                    State::Done
+                }
+            }
+            State::Done => State::Done,
+        };
+    }
+
+    fn execute(&mut self, command: u16) -> State {
+        let address = command as u8 & 0x7F;
+        match (command >> 8, command >> 6 & 0x03) {
+            // READ: a dummy 0 bit comes before the data
+            (0b10, _) => {
+                self.data_out = false;
+                State::Read {
+                    address,
+                    word: self.word(address),
+                    bits: WORD_BITS,
+                }
+            }
+            // WRITE
+            (0b01, _) => State::Write {
+                address: Some(address),
+                value: 0,
+                bits: 0,
+            },
+            // ERASE
+            (0b11, _) => {
+                self.set_word(address, 0xFFFF);
+                State::Done
+            }
+            // EWEN
+            (0b00, 0b11) => {
+                self.write_enabled = true;
+                State::Done
+            }
+            // EWDS
+            (0b00, 0b00) => {
+                self.write_enabled = false;
+                State::Done
+            }
+            // ERAL
+            (0b00, 0b10) => {
+                (0..0x80).for_each(|address| self.set_word(address, 0xFFFF));
+                State::Done
+            }
+            // WRAL
+            _ => State::Write {
+                address: None,
+                value: 0,
+                bits: 0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clocks `bits` into the EEPROM, most significant first, and returns
+    // the DO values seen after each rising edge
+    fn send(eeprom: &mut EEPROM, value: u32, bits: u8) -> u32 {
+        let mut output = 0;
+        for bit in (0..bits).rev() {
+            let di = ((value >> bit) & 0x01) as u8;
+            eeprom.write_pins(0x80 | di << 1);
+            eeprom.write_pins(0xC0 | di << 1);
+            output = output << 1 | (eeprom.read_pins() & 0x01) as u32;
+        }
+        output
+    }
+
+    // Selects the chip and sends the start bit, the opcode and the address
+    fn command(eeprom: &mut EEPROM, opcode: u32, address: u32) -> u32 {
+        eeprom.write_pins(0x00);
+        eeprom.write_pins(0x80);
+        send(eeprom, 0x400 | opcode << 8 | address, 11)
+    }
+
+    #[test]
+    fn writes_need_ewen() {
+        let mut eeprom = EEPROM::new();
+        // WRITE 0x1234 at word 3
+        command(&mut eeprom, 0b01, 0x03);
+        send(&mut eeprom, 0x1234, 16);
+        assert_eq!(eeprom.data[6..8], [0xFF, 0xFF]);
+
+        command(&mut eeprom, 0b00, 0xC0);
+        assert!(eeprom.write_enabled);
+        command(&mut eeprom, 0b01, 0x03);
+        send(&mut eeprom, 0x1234, 16);
+        assert_eq!(eeprom.data[6..8], [0x34, 0x12]);
+        assert_eq!(eeprom.read_pins() & 0x01, 0x01);
+    }
+
+    #[test]
+    fn read_sends_a_dummy_bit_then_sequential_words() {
+        let mut eeprom = EEPROM::new();
+        eeprom.data[0xFE..].copy_from_slice(&[0xCD, 0xAB]);
+        eeprom.data[..2].copy_from_slice(&[0x34, 0x12]);
+        let output = command(&mut eeprom, 0b10, 0x7F);
+        assert_eq!(output & 0x01, 0);
+        assert_eq!(send(&mut eeprom, 0, 16), 0xABCD);
+        // wraps around to word 0
+        assert_eq!(send(&mut eeprom, 0, 16), 0x1234);
+    }
+
+    #[test]
+    fn erase_and_write_all() {
+        let mut eeprom = EEPROM::new();
+        command(&mut eeprom, 0b00, 0xC0);
+        command(&mut eeprom, 0b00, 0x40);
+        send(&mut eeprom, 0x5AA5, 16);
+        assert!(eeprom.data.chunks(2).all(|word| word == [0xA5, 0x5A]));
+
+        command(&mut eeprom, 0b11, 0x01);
+        assert_eq!(eeprom.data[..4], [0xA5, 0x5A, 0xFF, 0xFF]);
+        command(&mut eeprom, 0b00, 0x80);
+        assert!(eeprom.data.iter().all(|&byte| byte == 0xFF));
+    }
+}
@@ -0,0 +1,236 @@
+use super::{restore, Mapper, ROM_BANK_SIZE};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+const LOGO_ADDRESS: usize = 0x0104;
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+const MULTICART_SIZE: usize = 0x100000;
+
+/// MBC1 memory bank controller, up to 2 MiB of ROM and 32 KiB of RAM.
+///
+/// The ROM bank is made of the 5 bit BANK1 register in the low bits and the
+/// 2 bit BANK2 register in the high bits. BANK2 also selects the RAM bank,
+/// and in mode 1 the bank mapped at 0x0000-0x3FFF.
+///
+/// MBC1M multicarts wire only 4 bits of BANK1 to the ROM, so BANK2 selects
+/// one of four 256 KiB games.
+pub struct MBC1 {
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub ram_enabled: bool, //0x0000-0x1FFF
+    pub bank1: u8,         //0x2000-0x3FFF, never 0
+    pub bank2: u8,         //0x4000-0x5FFF
+    pub mode: u8,          //0x6000-0x7FFF, 1 applies BANK2 to 0x0000-0x3FFF and RAM
+    pub multicart: bool,   //MBC1M wiring
+    pub battery: bool,
+}
+
+impl MBC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, multicart: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0x0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: 0,
+            multicart,
+            battery: false,
+        }
+    }
+
+    /// MBC1M multicarts are 1 MiB images where each 256 KiB game starts
+    /// with its own header, so the Nintendo logo shows up again at the
+    /// start of the second game.
+    pub fn is_multicart(rom: &[u8]) -> bool {
+        let has_logo =
+            |address: usize| rom[address..address + NINTENDO_LOGO.len()] == NINTENDO_LOGO;
+        rom.len() == MULTICART_SIZE
+            && has_logo(LOGO_ADDRESS)
+            && has_logo(0x10 * ROM_BANK_SIZE + LOGO_ADDRESS)
+    }
+
+    fn upper_bits(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        (self.bank2 as usize) << shift
+    }
+
+    fn rom_offset(&self, bank: usize, address: u16) -> usize {
+        let bank = bank % (self.rom.len() / ROM_BANK_SIZE);
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = if self.mode == 1 {
+            self.bank2 as usize
+        } else {
+            0
+        };
+        let offset = bank * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mapper for MBC1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF if self.mode == 1 => self.upper_bits(),
+            0x0000..=0x3FFF => 0,
+            _ => {
+                let bank1 = if self.multicart {
+                    self.bank1 & 0x0F
+                } else {
+                    self.bank1
+                };
+                self.upper_bits() | bank1 as usize
+            }
+        };
+        self.rom[self.rom_offset(bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // bank 0 can't be selected here, writing 0 selects bank 1. The
+            // check covers all 5 bits even on MBC1M, so 0x10 selects bank 0
+            // of the current game there.
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn bank_0_selects_bank_1() {
+        let mut mbc = MBC1::new(numbered_rom(64), 0, false);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        // only the 5 low bits are checked: 0x20 is bank 0 with BANK2 = 0
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn bank2_selects_the_upper_rom_bits() {
+        let mut mbc = MBC1::new(numbered_rom(128), 0, false);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+
+        // mode 1 also maps bank 0x20 at 0x0000
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+
+        // banks past the end of the ROM wrap around
+        let mut small = MBC1::new(numbered_rom(8), 0, false);
+        small.write_rom(0x2000, 0x09);
+        assert_eq!(small.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn ram_needs_to_be_enabled() {
+        let mut mbc = MBC1::new(numbered_rom(4), 0x8000, false);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn ram_banks_are_selected_in_mode_1() {
+        let mut mbc = MBC1::new(numbered_rom(4), 0x8000, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x11);
+        // mode 0 always uses RAM bank 0
+        assert_eq!(mbc.ram[0x0000], 0x11);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_ram(0xA000, 0x22);
+        assert_eq!(mbc.ram[2 * RAM_BANK_SIZE], 0x22);
+        assert_eq!(mbc.read_ram(0xA000), 0x22);
+    }
+
+    #[test]
+    fn multicart_uses_4_bits_of_bank1() {
+        let mut mbc = MBC1::new(numbered_rom(64), 0, true);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x02);
+        assert_eq!(mbc.read_rom(0x4000), 0x12);
+        // 0x10 passes the zero check but maps to the first bank of the game
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(0x4000), 0x10);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x10);
+    }
+
+    #[test]
+    fn multicart_is_detected_from_the_second_logo() {
+        let mut rom = numbered_rom(64);
+        rom[LOGO_ADDRESS..LOGO_ADDRESS + 48].copy_from_slice(&NINTENDO_LOGO);
+        assert!(!MBC1::is_multicart(&rom));
+
+        let second_game = 0x10 * ROM_BANK_SIZE + LOGO_ADDRESS;
+        rom[second_game..second_game + 48].copy_from_slice(&NINTENDO_LOGO);
+        assert!(MBC1::is_multicart(&rom));
+        assert!(!MBC1::is_multicart(&rom[..0x80000]));
+    }
+
+    #[test]
+    fn matching_padding_is_not_a_multicart() {
+        assert!(!MBC1::is_multicart(&vec![0x00; MULTICART_SIZE]));
+        assert!(!MBC1::is_multicart(&vec![0xFF; MULTICART_SIZE]));
+    }
+}
@@ -0,0 +1,137 @@
+use super::{restore, Mapper, ROM_BANK_SIZE};
+
+const RAM_SIZE: usize = 0x200;
+
+/// MBC2 memory bank controller, up to 256 KiB of ROM and 512 4 bit RAM
+/// cells built into the controller.
+///
+/// Both registers live at 0x0000-0x3FFF and bit 8 of the address selects
+/// which one is written. The RAM is mirrored through 0xA000-0xBFFF and its
+/// upper nibble reads as 1s.
+pub struct MBC2 {
+    pub rom: Vec<u8>,
+    pub ram: [u8; RAM_SIZE], //only the low nibble of each cell is stored
+    pub ram_enabled: bool,
+    pub rom_bank: u8, //4 bits, never 0
+    pub battery: bool,
+}
+
+impl MBC2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            ram: [0x0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+            battery: false,
+        }
+    }
+}
+
+impl Mapper for MBC2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / ROM_BANK_SIZE),
+        };
+        self.rom[bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF if address & 0x0100 == 0 => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+            }
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ram_enabled {
+            self.ram[address as usize % RAM_SIZE] | 0xF0
+        } else {
+            0xFF
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            self.ram[address as usize % RAM_SIZE] = value & 0x0F;
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    // one byte per cell, the format used by other emulators
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.ram, data);
+        for cell in self.ram.iter_mut() {
+            *cell &= 0x0F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn address_bit_8_selects_the_register() {
+        let mut mbc = MBC2::new(numbered_rom(16));
+        // bit 8 set: ROM bank
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        assert!(!mbc.ram_enabled);
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        // bit 8 clear: RAM enable, even in the upper half
+        mbc.write_rom(0x3000, 0x0A);
+        assert!(mbc.ram_enabled);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        // writes to 0x4000-0x7FFF do nothing
+        mbc.write_rom(0x4100, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn ram_is_4_bits_wide_and_mirrored() {
+        let mut mbc = MBC2::new(numbered_rom(2));
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xF2);
+        assert_eq!(mbc.read_ram(0xA200), 0xF2);
+        assert_eq!(mbc.read_ram(0xBE00), 0xF2);
+
+        mbc.write_ram(0xBFFF, 0x0C);
+        assert_eq!(mbc.read_ram(0xA1FF), 0xFC);
+    }
+
+    #[test]
+    fn battery_saves_every_cell() {
+        let mut mbc = MBC2::new(numbered_rom(2));
+        assert_eq!(mbc.save_data(), None);
+        mbc.battery = true;
+        mbc.load_save_data(&[0xFA; RAM_SIZE]);
+        let data = mbc.save_data().unwrap();
+        assert_eq!(data.len(), RAM_SIZE);
+        assert!(data.iter().all(|&cell| cell == 0x0A));
+    }
+}
@@ -0,0 +1,192 @@
+use super::rtc::RTC;
+use super::{restore, Mapper, ROM_BANK_SIZE};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// MBC3 memory bank controller, up to 2 MiB of ROM, 32 KiB of RAM and an
+/// optional real time clock.
+///
+/// 0x4000-0x5FFF selects either a RAM bank (0x00-0x03) or an RTC register
+/// (0x08-0x0C) to map at 0xA000-0xBFFF. Writing 0x00 then 0x01 to
+/// 0x6000-0x7FFF latches the clock into the registers the game reads.
+pub struct MBC3 {
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub ram_enabled: bool, //0x0000-0x1FFF, also enables the RTC
+    pub rom_bank: u8,      //0x2000-0x3FFF, 7 bits, never 0
+    pub ram_bank: u8,      //0x4000-0x5FFF, RAM bank or RTC register
+    pub rtc: Option<RTC>,
+    pub battery: bool,
+}
+
+impl MBC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, rtc: Option<RTC>) -> Self {
+        Self {
+            rom,
+            ram: vec![0x0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc,
+            battery: false,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() || self.ram_bank > 0x03 {
+            return None;
+        }
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+
+    fn rtc_register(&self) -> Option<u8> {
+        match self.ram_bank {
+            0x08..=0x0C if self.ram_enabled && self.rtc.is_some() => Some(self.ram_bank),
+            _ => None,
+        }
+    }
+}
+
+impl Mapper for MBC3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / ROM_BANK_SIZE),
+        };
+        self.rom[bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value,
+            _ => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if let (Some(register), Some(rtc)) = (self.rtc_register(), self.rtc.as_ref()) {
+            return rtc.read(register);
+        }
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(register) = self.rtc_register() {
+            if let Some(rtc) = self.rtc.as_mut() {
+                rtc.write(register, value);
+            }
+        } else if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+    }
+
+    // the RTC state follows the RAM in the VBA/BGB footer format
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| {
+            let mut data = self.ram.clone();
+            if let Some(rtc) = self.rtc.as_ref() {
+                data.extend(rtc.footer());
+            }
+            data
+        })
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.ram, data);
+        if let Some(rtc) = self.rtc.as_mut() {
+            // saves without a footer leave the clock as is
+            rtc.load_footer(data.get(self.ram.len()..).unwrap_or(&[]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rtc::{RTCClock, FOOTER_SIZE};
+    use super::super::tests::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn rom_bank_uses_7_bits() {
+        let mut mbc = MBC3::new(numbered_rom(128), 0, None);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(0x4000), 0x7F);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn bank_register_selects_ram_or_rtc() {
+        let mut mbc = MBC3::new(numbered_rom(4), 0x8000, Some(RTC::new(RTCClock::Emulated)));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.ram[3 * RAM_BANK_SIZE], 0x42);
+
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 30);
+        assert_eq!(mbc.read_ram(0xBFFF), 30);
+        assert_eq!(mbc.ram[3 * RAM_BANK_SIZE], 0x42);
+
+        // the clock keeps counting the emulated time, visible after a latch
+        for _ in 0..60 * crate::CYCLES_PER_SECOND / 4 {
+            mbc.tick(4);
+        }
+        assert_eq!(mbc.read_ram(0xA000), 30);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 31);
+
+        // without a clock the registers read as open bus
+        let mut mbc = MBC3::new(numbered_rom(4), 0x2000, None);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn save_data_ends_with_the_rtc_footer() {
+        let mut mbc = MBC3::new(numbered_rom(4), 0x2000, Some(RTC::new(RTCClock::Emulated)));
+        mbc.battery = true;
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 12);
+        let data = mbc.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000 + FOOTER_SIZE);
+
+        let mut restored = MBC3::new(numbered_rom(4), 0x2000, Some(RTC::new(RTCClock::Emulated)));
+        restored.load_save_data(&data);
+        restored.write_rom(0x0000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 0x42);
+        restored.write_rom(0x4000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 12);
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+
+use super::{restore, Mapper, RumbleEvent, ROM_BANK_SIZE};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+const MOTOR_BIT: u8 = 0x08;
+// games toggle the motor many times a second, only the latest events are
+// kept for hosts that don't drain them
+const MAX_RUMBLE_EVENTS: usize = 256;
+
+/// MBC5 memory bank controller, up to 8 MiB of ROM and 128 KiB of RAM.
+///
+/// The 9 bit ROM bank is written in two parts, the low 8 bits at
+/// 0x2000-0x2FFF and bit 8 at 0x3000-0x3FFF. Unlike the older controllers
+/// bank 0 can be mapped at 0x4000-0x7FFF. On rumble cartridges bit 3 of the
+/// RAM bank register drives the motor instead of selecting a RAM bank.
+pub struct MBC5 {
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub ram_enabled: bool, //0x0000-0x1FFF
+    pub rom_bank: u16,     //0x2000-0x3FFF, 9 bits
+    pub ram_bank: u8,      //0x4000-0x5FFF, 4 bits, 3 on rumble cartridges
+    pub rumble: bool,      //rumble cartridge wiring
+    pub motor_on: bool,
+    pub battery: bool,
+    cycles: u64, //T-cycles since power on, timestamps the rumble events
+    events: VecDeque<RumbleEvent>,
+}
+
+impl MBC5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, rumble: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0x0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble,
+            motor_on: false,
+            battery: false,
+            cycles: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn write_ram_bank(&mut self, value: u8) {
+        if !self.rumble {
+            self.ram_bank = value & 0x0F;
+            return;
+        }
+        self.ram_bank = value & 0x07;
+        let motor_on = value & MOTOR_BIT != 0;
+        if motor_on != self.motor_on {
+            self.motor_on = motor_on;
+            if self.events.len() == MAX_RUMBLE_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(RumbleEvent {
+                cycle: self.cycles,
+                on: motor_on,
+            });
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mapper for MBC5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / ROM_BANK_SIZE),
+        };
+        self.rom[bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            // all 8 bits are checked, 0x1A doesn't enable the RAM
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => self.write_ram_bank(value),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+    }
+
+    fn take_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        self.events.drain(..).collect()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::numbered_rom;
+    use super::*;
+
+    #[test]
+    fn rom_bank_has_9_bits_and_can_be_0() {
+        let mut mbc = MBC5::new(numbered_rom(512), 0, false);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+
+        mbc.write_rom(0x3000, 0x01);
+        mbc.write_rom(0x2FFF, 0x23);
+        assert_eq!((mbc.read_rom(0x4000), mbc.read_rom(0x4001)), (0x23, 0x01));
+        // only bit 0 of the high register is used
+        mbc.write_rom(0x3FFF, 0xFE);
+        assert_eq!((mbc.read_rom(0x4000), mbc.read_rom(0x4001)), (0x23, 0x00));
+    }
+
+    #[test]
+    fn sixteen_ram_banks() {
+        let mut mbc = MBC5::new(numbered_rom(4), 0x20000, false);
+        mbc.write_rom(0x0000, 0x1A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.ram[15 * RAM_BANK_SIZE], 0x42);
+        assert!(mbc.take_rumble_events().is_empty());
+    }
+
+    #[test]
+    fn rumble_bit_drives_the_motor() {
+        let mut mbc = MBC5::new(numbered_rom(4), 0x8000, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.tick(4);
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.ram[RAM_BANK_SIZE], 0x42);
+        assert!(mbc.motor_on);
+
+        // writing the same state again doesn't repeat the event
+        mbc.write_rom(0x4000, 0x08);
+        mbc.tick(8);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(
+            mbc.take_rumble_events(),
+            vec![
+                RumbleEvent { cycle: 4, on: true },
+                RumbleEvent {
+                    cycle: 12,
+                    on: false
+                },
+            ]
+        );
+        assert!(mbc.take_rumble_events().is_empty());
+    }
+
+    #[test]
+    fn undrained_events_are_capped() {
+        let mut mbc = MBC5::new(numbered_rom(4), 0, true);
+        for _ in 0..MAX_RUMBLE_EVENTS {
+            mbc.write_rom(0x4000, 0x08);
+            mbc.tick(4);
+            mbc.write_rom(0x4000, 0x00);
+        }
+        let events = mbc.take_rumble_events();
+        assert_eq!(events.len(), MAX_RUMBLE_EVENTS);
+        // the oldest half was dropped
+        assert_eq!(events[0].cycle, MAX_RUMBLE_EVENTS as u64 / 2 * 4);
+        assert!(events[0].on);
+    }
+}
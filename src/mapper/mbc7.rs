@@ -0,0 +1,208 @@
+use super::eeprom::EEPROM;
+use super::{restore, Mapper, ROM_BANK_SIZE};
+
+// accelerometer reading when the cartridge is flat, and the change for 1 g
+const ACCELEROMETER_CENTER: f64 = 0x81D0 as f64;
+const ACCELEROMETER_SCALE: f64 = 0x70 as f64;
+const ERASED_READING: u16 = 0x8000;
+
+/// MBC7 memory bank controller with a 2 axis accelerometer and a 93LC56
+/// EEPROM, used by Kirby Tilt 'n' Tumble.
+///
+/// Instead of RAM, 0xA000-0xAFFF maps registers selected by bits 4-7 of the
+/// address once both 0x0A was written to 0x0000-0x1FFF and 0x40 to
+/// 0x4000-0x5FFF. Writing 0x55 to Ax0x then 0xAA to Ax1x latches the
+/// accelerometer into Ax2x-Ax5x, and Ax8x drives the EEPROM pins.
+pub struct MBC7 {
+    pub rom: Vec<u8>,
+    pub ram_enabled: bool,  //0x0000-0x1FFF
+    pub ram_unlocked: bool, //0x4000-0x5FFF
+    pub rom_bank: u8,       //0x2000-0x3FFF, 7 bits
+    pub tilt_x: f64,        //acceleration in g, set by the host
+    pub tilt_y: f64,
+    pub latched_x: u16,
+    pub latched_y: u16,
+    pub latch_ready: bool, //0x55 was written to Ax0x
+    pub eeprom: EEPROM,
+}
+
+impl MBC7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            ram_enabled: false,
+            ram_unlocked: false,
+            rom_bank: 1,
+            tilt_x: 0.0,
+            tilt_y: 0.0,
+            latched_x: ERASED_READING,
+            latched_y: ERASED_READING,
+            latch_ready: false,
+            eeprom: EEPROM::new(),
+        }
+    }
+
+    fn reading(tilt: f64) -> u16 {
+        (ACCELEROMETER_CENTER + ACCELEROMETER_SCALE * tilt).clamp(0.0, u16::MAX as f64) as u16
+    }
+
+    fn registers_enabled(&self, address: u16) -> bool {
+        self.ram_enabled && self.ram_unlocked && address < 0xB000
+    }
+}
+
+impl Mapper for MBC7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % (self.rom.len() / ROM_BANK_SIZE),
+        };
+        self.rom[bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_unlocked = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_enabled(address) {
+            return 0xFF;
+        }
+        match address >> 4 & 0x0F {
+            0x2 => self.latched_x as u8,
+            0x3 => (self.latched_x >> 8) as u8,
+            0x4 => self.latched_y as u8,
+            0x5 => (self.latched_y >> 8) as u8,
+            0x6 => 0x00, //Z axis, not connected
+            0x8 => self.eeprom.read_pins(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_enabled(address) {
+            return;
+        }
+        match address >> 4 & 0x0F {
+            0x0 if value == 0x55 => {
+                self.latched_x = ERASED_READING;
+                self.latched_y = ERASED_READING;
+                self.latch_ready = true;
+            }
+            0x1 if value == 0xAA && self.latch_ready => {
+                self.latched_x = Self::reading(self.tilt_x);
+                self.latched_y = Self::reading(self.tilt_y);
+                self.latch_ready = false;
+            }
+            0x8 => self.eeprom.write_pins(value),
+            _ => {}
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn set_tilt(&mut self, x: f64, y: f64) {
+        self.tilt_x = x;
+        self.tilt_y = y;
+    }
+
+    // the EEPROM keeps its contents without a battery, but it is saved
+    // the same way
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.data.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        restore(&mut self.eeprom.data, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::eeprom::EEPROM_SIZE;
+    use super::*;
+
+    fn enabled_mbc7() -> MBC7 {
+        let mut mbc = MBC7::new(vec![0x0; 4 * ROM_BANK_SIZE]);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    #[test]
+    fn registers_need_both_enables() {
+        let mut mbc = MBC7::new(vec![0x0; 4 * ROM_BANK_SIZE]);
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.read_ram(0xA060), 0xFF);
+        mbc.write_rom(0x4000, 0x40);
+        assert_eq!(mbc.read_ram(0xA060), 0x00);
+        assert_eq!(mbc.read_ram(0xA070), 0xFF);
+        assert_eq!(mbc.read_ram(0xB060), 0xFF);
+    }
+
+    #[test]
+    fn accelerometer_is_latched() {
+        let mut mbc = enabled_mbc7();
+        mbc.set_tilt(1.0, -0.5);
+        // 0xAA alone doesn't latch
+        mbc.write_ram(0xA010, 0xAA);
+        assert_eq!(mbc.read_ram(0xA020), 0x00);
+        assert_eq!(mbc.read_ram(0xA030), 0x80);
+
+        mbc.write_ram(0xA000, 0x55);
+        mbc.write_ram(0xA010, 0xAA);
+        let x = mbc.read_ram(0xA020) as u16 | (mbc.read_ram(0xA030) as u16) << 8;
+        let y = mbc.read_ram(0xA040) as u16 | (mbc.read_ram(0xA050) as u16) << 8;
+        assert_eq!((x, y), (0x81D0 + 0x70, 0x81D0 - 0x38));
+
+        // the latch keeps its value when the cartridge moves
+        mbc.set_tilt(0.0, 0.0);
+        assert_eq!(mbc.read_ram(0xA02F), 0x40);
+    }
+
+    // Selects the EEPROM through Ax8x and clocks in `bits` of `value`, most
+    // significant first
+    fn send_pins(mbc: &mut MBC7, value: u32, bits: u8) {
+        for bit in (0..bits).rev() {
+            let di = ((value >> bit) & 0x01) as u8;
+            mbc.write_ram(0xA080, 0x80 | di << 1);
+            mbc.write_ram(0xA080, 0xC0 | di << 1);
+        }
+    }
+
+    fn command_pins(mbc: &mut MBC7, opcode: u32, address: u32) {
+        mbc.write_ram(0xA080, 0x00);
+        mbc.write_ram(0xA080, 0x80);
+        send_pins(mbc, 0x400 | opcode << 8 | address, 11);
+    }
+
+    #[test]
+    fn eeprom_is_saved() {
+        let mut mbc = enabled_mbc7();
+        // EWEN, then WRITE 0xBEEF at word 0
+        command_pins(&mut mbc, 0b00, 0xC0);
+        command_pins(&mut mbc, 0b01, 0x00);
+        send_pins(&mut mbc, 0xBEEF, 16);
+        // DO reports ready once the write is done
+        assert_eq!(mbc.read_ram(0xA080) & 0x01, 0x01);
+
+        let data = mbc.save_data().unwrap();
+        assert_eq!(data.len(), EEPROM_SIZE);
+        assert_eq!(data[..4], [0xEF, 0xBE, 0xFF, 0xFF]);
+
+        let mut restored = MBC7::new(vec![0x0; 4 * ROM_BANK_SIZE]);
+        restored.load_save_data(&data);
+        assert_eq!(restored.save_data(), Some(data));
+    }
+}
@@ -0,0 +1,340 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::CYCLES_PER_SECOND;
+
+/// Size of the RTC footer appended to .sav files by VBA-M and BGB
+pub const FOOTER_SIZE: usize = 48;
+// older VBA versions store the timestamp on 32 bits
+const SHORT_FOOTER_SIZE: usize = 44;
+
+const SECONDS: usize = 0;
+const MINUTES: usize = 1;
+const HOURS: usize = 2;
+const DAYS_LOW: usize = 3;
+const DAYS_HIGH: usize = 4;
+
+const HALT_FLAG: u8 = 0x40;
+const DAY_CARRY_FLAG: u8 = 0x80;
+
+// mask of each register, unused bits read as 0
+const REGISTER_MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+/// Time source the RTC counts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RTCClock {
+    Host,     // wall clock time of the host
+    Emulated, // emulated CPU time, for reproducible runs
+}
+
+/// MBC3 real time clock. The registers are seconds, minutes, hours, the low
+/// 8 bits of the day counter and DH, which holds bit 8 of the day counter,
+/// the halt flag (bit 6) and the day counter carry (bit 7).
+#[derive(Clone, Debug)]
+pub struct RTC {
+    pub registers: [u8; 5],
+    pub latched: [u8; 5], //copy of the registers the game reads
+    pub clock: RTCClock,
+    time: u64,        //Unix time of the emulated clock, starts at the host time
+    last_update: u64, //clock time the registers were last brought up to date
+    cycles: u64,      //emulated T-cycles into the current second
+    latch_armed: bool,
+}
+
+impl RTC {
+    pub fn new(clock: RTCClock) -> Self {
+        let time = host_time();
+        Self {
+            registers: [0x0; 5],
+            latched: [0x0; 5],
+            clock,
+            time,
+            last_update: time,
+            cycles: 0,
+            latch_armed: false,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        match self.clock {
+            RTCClock::Host => host_time(),
+            RTCClock::Emulated => self.time,
+        }
+    }
+
+    /// Advances the emulated clock by `cycles` normal speed T-cycles
+    pub fn tick(&mut self, cycles: u8) {
+        if self.clock == RTCClock::Emulated {
+            self.cycles += cycles as u64;
+            if self.cycles >= CYCLES_PER_SECOND {
+                self.cycles -= CYCLES_PER_SECOND;
+                self.time += 1;
+            }
+        }
+    }
+
+    /// Counts the seconds elapsed since the last update, unless halted
+    pub fn update(&mut self) {
+        let now = self.now();
+        let elapsed = now.saturating_sub(self.last_update);
+        self.last_update = now;
+        if self.registers[DAYS_HIGH] & HALT_FLAG == 0 {
+            self.advance(elapsed);
+        }
+    }
+
+    // Registers written with out of range values count up to the top of
+    // their bits and wrap to 0 without carrying, so they are stepped one
+    // second at a time until they are valid again.
+    fn advance(&mut self, mut seconds: u64) {
+        while seconds > 0 && !self.is_valid() {
+            self.tick_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let total = self.registers[SECONDS] as u64
+            + self.registers[MINUTES] as u64 * 60
+            + self.registers[HOURS] as u64 * 3600
+            + self.days() * 86400
+            + seconds;
+        let days = total / 86400;
+        self.registers[SECONDS] = (total % 60) as u8;
+        self.registers[MINUTES] = (total / 60 % 60) as u8;
+        self.registers[HOURS] = (total / 3600 % 24) as u8;
+        self.set_days(days % 512);
+        if days >= 512 {
+            self.registers[DAYS_HIGH] |= DAY_CARRY_FLAG;
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.registers[SECONDS] < 60 && self.registers[MINUTES] < 60 && self.registers[HOURS] < 24
+    }
+
+    fn tick_second(&mut self) {
+        let carries = [(SECONDS, 60, 0x3F), (MINUTES, 60, 0x3F), (HOURS, 24, 0x1F)];
+        for (register, limit, mask) in carries {
+            self.registers[register] = (self.registers[register] + 1) & mask;
+            if self.registers[register] != limit {
+                // either no overflow, or an invalid value wrapping without carry
+                return;
+            }
+            self.registers[register] = 0;
+        }
+        let days = self.days() + 1;
+        if days == 512 {
+            self.registers[DAYS_HIGH] |= DAY_CARRY_FLAG;
+        }
+        self.set_days(days % 512);
+    }
+
+    fn days(&self) -> u64 {
+        ((self.registers[DAYS_HIGH] as u64 & 0x01) << 8) | self.registers[DAYS_LOW] as u64
+    }
+
+    fn set_days(&mut self, days: u64) {
+        self.registers[DAYS_LOW] = days as u8;
+        self.registers[DAYS_HIGH] = (self.registers[DAYS_HIGH] & !0x01) | (days >> 8) as u8;
+    }
+
+    /// Reads register 0x08-0x0C from the latched copy
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    /// Writes register 0x08-0x0C. The latched copy is updated as well so the
+    /// game reads back what it wrote.
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        let index = (register - 0x08) as usize;
+        let value = value & REGISTER_MASKS[index];
+        if index == SECONDS {
+            // writing the seconds resets the sub-second counter
+            self.cycles = 0;
+        }
+        self.registers[index] = value;
+        self.latched[index] = value;
+    }
+
+    /// Handles writes to 0x6000-0x7FFF: writing 0x00 then 0x01 copies the
+    /// registers into the latched copy.
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update();
+            self.latched = self.registers;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    /// Serializes the registers in the VBA/BGB footer format: the registers
+    /// and the latched registers as 32 bit little endian values followed by
+    /// a 64 bit Unix timestamp.
+    pub fn footer(&self) -> Vec<u8> {
+        let mut rtc = self.clone();
+        rtc.update();
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        for value in rtc.registers.iter().chain(rtc.latched.iter()) {
+            footer.extend((*value as u32).to_le_bytes());
+        }
+        footer.extend(rtc.last_update.to_le_bytes());
+        footer
+    }
+
+    /// Restores the registers from a 48 or 44 byte footer. The host clock
+    /// counts the time elapsed since it was saved, the emulated clock
+    /// carries on from the saved time. Returns false if `footer` has neither
+    /// size.
+    pub fn load_footer(&mut self, footer: &[u8]) -> bool {
+        let word = |index: usize| {
+            let bytes = [
+                footer[index * 4],
+                footer[index * 4 + 1],
+                footer[index * 4 + 2],
+                footer[index * 4 + 3],
+            ];
+            u32::from_le_bytes(bytes)
+        };
+        let timestamp = match footer.len() {
+            FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            SHORT_FOOTER_SIZE => word(10) as u64,
+            _ => return false,
+        };
+        for (index, mask) in REGISTER_MASKS.iter().enumerate() {
+            self.registers[index] = word(index) as u8 & mask;
+            self.latched[index] = word(index + 5) as u8 & mask;
+        }
+        if self.clock == RTCClock::Emulated {
+            self.time = timestamp;
+        }
+        self.last_update = timestamp;
+        self.update();
+        true
+    }
+}
+
+fn host_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_seconds(rtc: &mut RTC, seconds: u64) {
+        for _ in 0..seconds * CYCLES_PER_SECOND / 4 {
+            rtc.tick(4);
+        }
+    }
+
+    fn latch(rtc: &mut RTC) {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+    }
+
+    #[test]
+    fn emulated_clock_counts_cpu_time() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        run_seconds(&mut rtc, 2);
+        assert_eq!(rtc.read(0x08), 0);
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 2);
+    }
+
+    #[test]
+    fn counters_carry_into_days() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0A, 23);
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+        rtc.advance(1);
+        assert_eq!(rtc.registers, [0, 0, 0, 0x00, DAY_CARRY_FLAG]);
+
+        rtc.advance(86400 + 3661);
+        assert_eq!(rtc.registers, [1, 1, 1, 0x01, DAY_CARRY_FLAG]);
+    }
+
+    #[test]
+    fn invalid_values_wrap_without_carry() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        rtc.write(0x08, 0x3F);
+        rtc.advance(1);
+        assert_eq!(rtc.registers[SECONDS], 0);
+        assert_eq!(rtc.registers[MINUTES], 0);
+
+        rtc.write(0x0A, 0x1F);
+        rtc.advance(3600);
+        assert_eq!(rtc.registers[HOURS], 0);
+        assert_eq!(rtc.days(), 0);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        rtc.write(0x0C, HALT_FLAG);
+        run_seconds(&mut rtc, 1);
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 0);
+
+        rtc.write(0x0C, 0x00);
+        run_seconds(&mut rtc, 1);
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 1);
+    }
+
+    #[test]
+    fn latch_needs_0_then_1() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        rtc.write(0x09, 5);
+        rtc.registers[MINUTES] = 6;
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x09), 5);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x09), 6);
+    }
+
+    #[test]
+    fn footer_round_trip() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        rtc.write(0x09, 42);
+        rtc.write(0x0C, 0x01);
+        let footer = rtc.footer();
+        assert_eq!(footer.len(), FOOTER_SIZE);
+        assert_eq!(&footer[4..8], &[42, 0, 0, 0]);
+
+        let mut restored = RTC::new(RTCClock::Emulated);
+        assert!(restored.load_footer(&footer));
+        assert_eq!(restored.registers, rtc.registers);
+        assert_eq!(restored.latched, rtc.latched);
+        assert!(restored.load_footer(&footer[..SHORT_FOOTER_SIZE]));
+        assert!(!restored.load_footer(&footer[..10]));
+    }
+
+    #[test]
+    fn emulated_clock_saves_a_unix_timestamp() {
+        let mut rtc = RTC::new(RTCClock::Emulated);
+        let footer = rtc.footer();
+        let timestamp = u64::from_le_bytes(footer[40..48].try_into().unwrap());
+        assert!(timestamp.abs_diff(host_time()) < 60);
+
+        // a save from the past doesn't fast forward the emulated clock
+        let mut footer = footer;
+        footer[40..48].copy_from_slice(&1_000_000_000u64.to_le_bytes());
+        rtc.load_footer(&footer);
+        assert_eq!(rtc.registers, [0x0; 5]);
+        run_seconds(&mut rtc, 1);
+        let footer = rtc.footer();
+        assert_eq!(&footer[..4], &[1, 0, 0, 0]);
+        assert_eq!(
+            u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            1_000_000_001
+        );
+    }
+}
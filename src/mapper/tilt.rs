@@ -0,0 +1,112 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub enum TiltScriptError {
+    InvalidLine(usize),      //line number, starting at 1
+    FramesOutOfOrder(usize), //the frame is before the previous entry
+}
+
+impl fmt::Display for TiltScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiltScriptError::InvalidLine(line) => {
+                write!(f, "line {}: expected <frame> <x> <y>", line)
+            }
+            TiltScriptError::FramesOutOfOrder(line) => {
+                write!(f, "line {}: frame is before the previous entry", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiltScriptError {}
+
+/// Scripted tilt input for headless runs of accelerometer cartridges.
+///
+/// Each line holds a frame number and the X and Y tilt in g, applied from
+/// that frame until the next entry. Frames are in increasing order, blank
+/// lines and lines starting with # are ignored:
+///
+/// ```text
+/// # frame x y
+/// 0 0.0 0.0
+/// 600 -0.5 0.25
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct TiltScript {
+    pub entries: Vec<(u64, f64, f64)>,
+}
+
+impl TiltScript {
+    /// Reads a script file. Invalid scripts are reported as
+    /// `io::ErrorKind::InvalidData` errors wrapping a `TiltScriptError`.
+    pub fn open(path: &Path) -> io::Result<TiltScript> {
+        let text = fs::read_to_string(path)?;
+        TiltScript::parse(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn parse(text: &str) -> Result<TiltScript, TiltScriptError> {
+        let mut entries: Vec<(u64, f64, f64)> = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let entry = match fields[..] {
+                [frame, x, y] => match (frame.parse(), x.parse(), y.parse()) {
+                    (Ok(frame), Ok(x), Ok(y)) => (frame, x, y),
+                    _ => return Err(TiltScriptError::InvalidLine(index + 1)),
+                },
+                _ => return Err(TiltScriptError::InvalidLine(index + 1)),
+            };
+            if entries.last().is_some_and(|last| last.0 > entry.0) {
+                return Err(TiltScriptError::FramesOutOfOrder(index + 1));
+            }
+            entries.push(entry);
+        }
+        Ok(TiltScript { entries })
+    }
+
+    /// Tilt to apply during `frame`, `None` before the first entry
+    pub fn tilt_at(&self, frame: u64) -> Option<(f64, f64)> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.0 <= frame)
+            .map(|&(_, x, y)| (x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_apply_until_the_next_one() {
+        let script = TiltScript::parse("# frame x y\n10 0.5 -1\n\n20 0 0.25\n").unwrap();
+        assert_eq!(script.tilt_at(9), None);
+        assert_eq!(script.tilt_at(10), Some((0.5, -1.0)));
+        assert_eq!(script.tilt_at(19), Some((0.5, -1.0)));
+        assert_eq!(script.tilt_at(1000), Some((0.0, 0.25)));
+    }
+
+    #[test]
+    fn rejects_invalid_scripts() {
+        assert_eq!(
+            TiltScript::parse("0 0 0\n1 0\n"),
+            Err(TiltScriptError::InvalidLine(2))
+        );
+        assert_eq!(
+            TiltScript::parse("x 0 0\n"),
+            Err(TiltScriptError::InvalidLine(1))
+        );
+        assert_eq!(
+            TiltScript::parse("5 0 0\n4 0 0\n"),
+            Err(TiltScriptError::FramesOutOfOrder(2))
+        );
+    }
+}
@@ -1,22 +1,407 @@
-const MEMORY_SIZE: usize = 0xFFFF;
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::interrupt::{Interrupt, INTERRUPT_ENABLE_ADDRESS, INTERRUPT_FLAG_ADDRESS};
+use crate::mapper::{Mapper, RomOnly};
+use crate::model::Model;
+
+const VRAM_SIZE: usize = 0x2000;
+const WRAM_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+const IO_SIZE: usize = 0x80;
+const HRAM_SIZE: usize = 0x7F;
+
+const DIV_ADDRESS: u16 = 0xFF04;
+const KEY1_ADDRESS: u16 = 0xFF4D;
+const SERIAL_DATA_ADDRESS: u16 = 0xFF01;
+const SERIAL_CONTROL_ADDRESS: u16 = 0xFF02;
 
 pub struct MemoryBus {
-    pub memory: [u8; MEMORY_SIZE],
+    pub cartridge: Box<dyn Mapper>, //ROM (0x0000-0x7FFF) and external RAM (0xA000-0xBFFF)
+    pub vram: [u8; VRAM_SIZE],      //0x8000-0x9FFF
+    pub wram: [u8; WRAM_SIZE],      //0xC000-0xDFFF, mirrored at 0xE000-0xFDFF
+    pub oam: [u8; OAM_SIZE],        //0xFE00-0xFE9F
+    pub io: [u8; IO_SIZE],          //0xFF00-0xFF7F, registers without their own field
+    pub hram: [u8; HRAM_SIZE],      //0xFF80-0xFFFE
+    pub interrupt_enable: u8,       //IE (0xFFFF)
+    pub interrupt_flag: u8,         //IF (0xFF0F)
+    pub div_counter: u16,           //DIV (0xFF04) is the upper byte of this counter
+    pub cgb_mode: bool,
+    pub double_speed: bool,             //KEY1 (0xFF4D) bit 7
+    pub speed_switch_armed: bool,       //KEY1 (0xFF4D) bit 0
+    pub serial_output: Option<Vec<u8>>, // bytes sent over the serial port when set
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryBus {
     pub fn new() -> Self {
         Self {
-            memory: [0x0; MEMORY_SIZE],
+            cartridge: Box::new(RomOnly::default()),
+            vram: [0x0; VRAM_SIZE],
+            wram: [0x0; WRAM_SIZE],
+            oam: [0x0; OAM_SIZE],
+            io: [0x0; IO_SIZE],
+            hram: [0x0; HRAM_SIZE],
+            interrupt_enable: 0x0,
+            interrupt_flag: 0x0,
+            div_counter: 0x0,
+            cgb_mode: false,
+            double_speed: false,
+            speed_switch_armed: false,
+            serial_output: None,
         }
     }
 
+    /// Plugs `cartridge` into the ROM and external RAM areas
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = cartridge.into_mapper();
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+        match address {
+            0x0000..=0x7FFF => self.cartridge.read_rom(address),
+            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize],
+            0xA000..=0xBFFF => self.cartridge.read_ram(address),
+            0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize],
+            // echo RAM
+            0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize],
+            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize],
+            // unusable area, reads 0x00 on the DMG
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF00..=0xFF7F => self.read_io(address),
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
+            INTERRUPT_ENABLE_ADDRESS => self.interrupt_enable,
+        }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        let address = address as usize;
-        self.memory[address] = value;
+        match address {
+            // bank controller commands, the ROM itself is read only
+            0x0000..=0x7FFF => self.cartridge.write_rom(address, value),
+            0x8000..=0x9FFF => self.vram[(address - 0x8000) as usize] = value,
+            0xA000..=0xBFFF => self.cartridge.write_ram(address, value),
+            0xC000..=0xDFFF => self.wram[(address - 0xC000) as usize] = value,
+            0xE000..=0xFDFF => self.wram[(address - 0xE000) as usize] = value,
+            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
+            0xFEA0..=0xFEFF => {}
+            0xFF00..=0xFF7F => self.write_io(address, value),
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
+            INTERRUPT_ENABLE_ADDRESS => self.interrupt_enable = value,
+        }
+    }
+
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            // only the lower 5 bits of IF are wired, the rest read as 1
+            INTERRUPT_FLAG_ADDRESS => self.interrupt_flag | 0xE0,
+            DIV_ADDRESS => (self.div_counter >> 8) as u8,
+            KEY1_ADDRESS if self.cgb_mode => {
+                (if self.double_speed { 0x80 } else { 0x0 })
+                    | 0x7E
+                    | (if self.speed_switch_armed { 0x1 } else { 0x0 })
+            }
+            KEY1_ADDRESS => 0xFF,
+            _ => self.io[(address - 0xFF00) as usize],
+        }
+    }
+
+    fn write_io(&mut self, address: u16, value: u8) {
+        match address {
+            INTERRUPT_FLAG_ADDRESS => self.interrupt_flag = value & 0x1F,
+            // writing any value to DIV resets it
+            DIV_ADDRESS => self.reset_div(),
+            KEY1_ADDRESS if self.cgb_mode => self.speed_switch_armed = value & 0x1 != 0,
+            KEY1_ADDRESS => {}
+            SERIAL_CONTROL_ADDRESS => {
+                self.io[(address - 0xFF00) as usize] = value;
+                // a transfer started with the internal clock
+                if value & 0x81 == 0x81 {
+                    self.transfer_serial_byte();
+                }
+            }
+            _ => self.io[(address - 0xFF00) as usize] = value,
+        }
+    }
+
+    // Completes a serial transfer right away. Without a link partner the
+    // received byte is 0xFF.
+    fn transfer_serial_byte(&mut self) {
+        let byte = self.io[(SERIAL_DATA_ADDRESS - 0xFF00) as usize];
+        if let Some(output) = &mut self.serial_output {
+            output.push(byte);
+        }
+        self.io[(SERIAL_DATA_ADDRESS - 0xFF00) as usize] = 0xFF;
+        self.io[(SERIAL_CONTROL_ADDRESS - 0xFF00) as usize] &= 0x7F;
+        self.request_interrupt(Interrupt::Serial);
+    }
+
+    /// Sets the IO registers to the values left by the boot ROM of `model`.
+    /// DIV on SGB and CGB depends on how long the boot ROM ran, so the values
+    /// used for those models are only approximations.
+    pub fn reset_to_post_boot(&mut self, model: Model) {
+        self.cgb_mode = model.is_cgb();
+        self.double_speed = false;
+        self.speed_switch_armed = false;
+        self.interrupt_enable = 0x00;
+        self.interrupt_flag = 0x01;
+        self.div_counter = match model {
+            Model::DMG0 => 0x1830,
+            Model::DMG | Model::MGB => 0xABCC,
+            Model::SGB | Model::SGB2 => 0xD85C,
+            Model::CGB | Model::AGB => 0x2678,
+        };
+
+        let serial_control = if model.is_cgb() { 0x7F } else { 0x7E };
+        let sound_on = match model {
+            Model::SGB | Model::SGB2 => 0xF0,
+            _ => 0xF1,
+        };
+        let (lcd_status, ly) = match model {
+            Model::DMG0 => (0x81, 0x91),
+            _ => (0x85, 0x00),
+        };
+        let dma = if model.is_cgb() { 0x00 } else { 0xFF };
+
+        let io_registers: [(u16, u8); 37] = [
+            (0xFF00, 0xCF), // P1
+            (0xFF01, 0x00), // SB
+            (0xFF02, serial_control),
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (0xFF10, 0x80), // NR10
+            (0xFF11, 0xBF), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF13, 0xFF), // NR13
+            (0xFF14, 0xBF), // NR14
+            (0xFF16, 0x3F), // NR21
+            (0xFF17, 0x00), // NR22
+            (0xFF18, 0xFF), // NR23
+            (0xFF19, 0xBF), // NR24
+            (0xFF1A, 0x7F), // NR30
+            (0xFF1B, 0xFF), // NR31
+            (0xFF1C, 0x9F), // NR32
+            (0xFF1D, 0xFF), // NR33
+            (0xFF1E, 0xBF), // NR34
+            (0xFF20, 0xFF), // NR41
+            (0xFF21, 0x00), // NR42
+            (0xFF22, 0x00), // NR43
+            (0xFF23, 0xBF), // NR44
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF26, sound_on),
+            (0xFF40, 0x91), // LCDC
+            (0xFF41, lcd_status),
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF44, ly),
+            (0xFF45, 0x00), // LYC
+            (0xFF46, dma),
+            (0xFF47, 0xFC), // BGP
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+        ];
+        for (address, value) in io_registers {
+            self.write_byte(address, value);
+        }
+    }
+
+    /// Advances the components on the bus by `cycles` CPU T-cycles
+    pub fn tick(&mut self, cycles: u8) {
+        self.div_counter = self.div_counter.wrapping_add(cycles as u16);
+        // the cartridge clock doesn't follow the CPU into double speed
+        let cartridge_cycles = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.cartridge.tick(cartridge_cycles);
+    }
+
+    pub fn reset_div(&mut self) {
+        self.div_counter = 0;
+    }
+
+    /// Performs the CGB speed switch prepared through KEY1, called by STOP
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+    }
+
+    /// Sets the IF bit of `interrupt`, used by peripherals to signal the CPU
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.mask();
+    }
+
+    /// Clears the IF bit of `interrupt` once the CPU has serviced it
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag &= !interrupt.mask();
+    }
+
+    /// Highest priority interrupt that is both requested and enabled
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        Interrupt::highest_priority(self.interrupt_enable & self.interrupt_flag)
+    }
+}
+
+impl Bus for MemoryBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.write_byte(address, value);
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        MemoryBus::tick(self, cycles);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        MemoryBus::pending_interrupt(self)
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        MemoryBus::acknowledge_interrupt(self, interrupt);
+    }
+
+    // STOP resets DIV, and on CGB performs the speed switch prepared through
+    // KEY1 instead of entering low power mode
+    fn stop(&mut self) -> bool {
+        self.reset_div();
+        if self.speed_switch_armed {
+            self.switch_speed();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn can_write_interrupt_enable() {
+        let mut bus = MemoryBus::new();
+        bus.write_byte(0xFFFF, 0b0000_0101);
+        assert_eq!(bus.read_byte(0xFFFF), 0b0000_0101);
+    }
+
+    #[test]
+    fn rom_writes_go_to_the_mapper() {
+        let mut bus = MemoryBus::new();
+        bus.cartridge.rom_mut()[0x0150] = 0x42;
+        bus.write_byte(0x0150, 0x00);
+        assert_eq!(bus.read_byte(0x0150), 0x42);
+    }
+
+    #[test]
+    fn echo_ram_mirrors_work_ram() {
+        let mut bus = MemoryBus::new();
+        bus.write_byte(0xC123, 0x12);
+        assert_eq!(bus.read_byte(0xE123), 0x12);
+        bus.write_byte(0xFDFF, 0x34);
+        assert_eq!(bus.read_byte(0xDDFF), 0x34);
+    }
+
+    #[test]
+    fn unusable_area_ignores_writes() {
+        let mut bus = MemoryBus::new();
+        bus.write_byte(0xFEA0, 0x12);
+        assert_eq!(bus.read_byte(0xFEA0), 0x00);
+        bus.write_byte(0xFE9F, 0x12);
+        assert_eq!(bus.read_byte(0xFE9F), 0x12);
+    }
+
+    #[test]
+    fn every_address_is_mapped() {
+        let mut bus = MemoryBus::new();
+        for address in 0x0000..=0xFFFF {
+            bus.write_byte(address, bus.read_byte(address));
+        }
+        bus.write_byte(0xFF80, 0x56);
+        bus.write_byte(0xFFFE, 0x78);
+        assert_eq!(bus.hram[0], 0x56);
+        assert_eq!(bus.hram[HRAM_SIZE - 1], 0x78);
+    }
+
+    #[test]
+    fn request_interrupt_sets_if_bit() {
+        let mut bus = MemoryBus::new();
+        bus.request_interrupt(Interrupt::Timer);
+        assert_eq!(bus.read_byte(0xFF0F), 0b1110_0100);
+        assert_eq!(bus.pending_interrupt(), None);
+        bus.interrupt_enable = Interrupt::Timer.mask();
+        assert_eq!(bus.pending_interrupt(), Some(Interrupt::Timer));
+    }
+
+    #[test]
+    fn div_counts_every_256_cycles_and_resets_on_write() {
+        let mut bus = MemoryBus::new();
+        for _ in 0..64 {
+            bus.tick(4);
+        }
+        assert_eq!(bus.read_byte(0xFF04), 1);
+        bus.write_byte(0xFF04, 0x42);
+        assert_eq!(bus.read_byte(0xFF04), 0);
+        assert_eq!(bus.div_counter, 0);
+    }
+
+    #[test]
+    fn post_boot_io_registers() {
+        let mut bus = MemoryBus::new();
+        bus.reset_to_post_boot(Model::DMG);
+        assert_eq!(bus.read_byte(0xFF04), 0xAB);
+        assert_eq!(bus.read_byte(0xFF0F), 0xE1);
+        assert_eq!(bus.read_byte(0xFF40), 0x91);
+        assert_eq!(bus.read_byte(0xFF26), 0xF1);
+        assert_eq!(bus.read_byte(0xFF4D), 0xFF);
+        assert_eq!(bus.read_byte(0xFFFF), 0x00);
+
+        bus.reset_to_post_boot(Model::CGB);
+        assert_eq!(bus.read_byte(0xFF02), 0x7F);
+        assert_eq!(bus.read_byte(0xFF4D), 0x7E);
+    }
+
+    #[test]
+    fn key1_is_only_available_in_cgb_mode() {
+        let mut bus = MemoryBus::new();
+        bus.write_byte(0xFF4D, 0x1);
+        assert_eq!(bus.read_byte(0xFF4D), 0xFF);
+        assert!(!bus.speed_switch_armed);
+
+        bus.cgb_mode = true;
+        assert_eq!(bus.read_byte(0xFF4D), 0x7E);
+        bus.write_byte(0xFF4D, 0x1);
+        assert_eq!(bus.read_byte(0xFF4D), 0x7F);
+        bus.switch_speed();
+        assert_eq!(bus.read_byte(0xFF4D), 0xFE);
+    }
+
+    #[test]
+    fn serial_transfer_is_captured() {
+        let mut bus = MemoryBus::new();
+        bus.serial_output = Some(Vec::new());
+        bus.write_byte(SERIAL_DATA_ADDRESS, b'P');
+        bus.write_byte(SERIAL_CONTROL_ADDRESS, 0x81);
+
+        assert_eq!(bus.serial_output, Some(vec![b'P']));
+        assert_eq!(bus.read_byte(SERIAL_CONTROL_ADDRESS) & 0x80, 0);
+        assert_eq!(bus.read_byte(SERIAL_DATA_ADDRESS), 0xFF);
+        assert_eq!(bus.pending_interrupt(), None);
+        assert_eq!(bus.interrupt_flag, Interrupt::Serial.mask());
     }
 }
@@ -0,0 +1,17 @@
+/// Game Boy hardware revisions, they differ in the state left by the boot ROM
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Model {
+    DMG0, // early original Game Boy
+    DMG,  // original Game Boy
+    MGB,  // Game Boy Pocket
+    SGB,  // Super Game Boy
+    SGB2, // Super Game Boy 2
+    CGB,  // Game Boy Color
+    AGB,  // Game Boy Advance
+}
+
+impl Model {
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::CGB | Model::AGB)
+    }
+}
@@ -0,0 +1,43 @@
+//! Runners for the community test ROM suites
+
+pub mod blargg;
+pub mod mooneye;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::cartridge::Cartridge;
+use crate::cpu::{StepError, CPU};
+use crate::model::Model;
+
+/// Result of running a test ROM
+#[derive(Clone, Debug, PartialEq)]
+pub enum TestResult {
+    Passed,
+    Failed,
+    TimedOut,
+    Crashed(StepError),
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestResult::Passed => write!(f, "passed"),
+            TestResult::Failed => write!(f, "failed"),
+            TestResult::TimedOut => write!(f, "timed out"),
+            TestResult::Crashed(error) => write!(f, "crashed: {}", error),
+        }
+    }
+}
+
+/// Creates a CPU for `model` in its post-boot state with the cartridge at
+/// `path` loaded
+pub fn load_rom(path: &Path, model: Model) -> io::Result<CPU> {
+    let cartridge = Cartridge::open(path)?;
+    let mut cpu = CPU::new();
+    cpu.bus.load_cartridge(cartridge);
+    // the post-boot flags depend on the header checksum of the cartridge
+    cpu.reset_to_post_boot(model);
+    Ok(cpu)
+}
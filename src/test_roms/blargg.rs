@@ -0,0 +1,103 @@
+//! Runner for Blargg's test ROMs (cpu_instrs, instr_timing, ...), which
+//! print their results over the serial port.
+
+use std::path::Path;
+
+use super::{load_rom, TestResult};
+use crate::cpu::CPU;
+use crate::model::Model;
+
+/// Runs a Blargg ROM until it prints `Passed` or `Failed` over the serial
+/// port, or until `timeout_cycles` T-cycles have been emulated. Returns the
+/// result and the text printed by the ROM.
+pub fn run(cpu: &mut CPU, timeout_cycles: u64) -> (TestResult, String) {
+    cpu.bus.serial_output = Some(Vec::new());
+    let start = cpu.cycles;
+
+    let result = loop {
+        if let Err(error) = crate::run_frame(cpu) {
+            break TestResult::Crashed(error);
+        }
+        let output = cpu.bus.serial_output.as_deref().unwrap_or_default();
+        let output = String::from_utf8_lossy(output);
+        if output.contains("Passed") {
+            break TestResult::Passed;
+        }
+        if output.contains("Failed") {
+            // keep running a bit so the ROM can print which tests failed
+            let _ = crate::run_for_cycles(cpu, crate::CYCLES_PER_SECOND);
+            break TestResult::Failed;
+        }
+        if cpu.cycles - start >= timeout_cycles {
+            break TestResult::TimedOut;
+        }
+    };
+
+    let output = cpu.bus.serial_output.take().unwrap_or_default();
+    (result, String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Loads and runs the Blargg ROM at `path`, see `run`
+pub fn run_rom(path: &Path, timeout_cycles: u64) -> std::io::Result<(TestResult, String)> {
+    let mut cpu = load_rom(path, Model::DMG)?;
+    Ok(run(&mut cpu, timeout_cycles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_at;
+
+    // prints the zero terminated string at `message` over the serial port
+    fn rom_printing(message: &str) -> CPU {
+        let program = assemble_at(
+            &format!(
+                "
+                    ld hl, message
+                print:
+                    ld a, [hl+]
+                    and a
+                    jr z, done
+                    ldh [$FF01], a
+                    ld a, $81
+                    ldh [$FF02], a
+                    jr print
+                done:
+                    jr done
+                message:
+                    db \"{}\", 0
+                ",
+                message
+            ),
+            0x100,
+        )
+        .unwrap();
+        let mut cpu = CPU::with_model(Model::DMG);
+        cpu.bus.cartridge.rom_mut()[0x100..0x100 + program.len()].copy_from_slice(&program);
+        cpu
+    }
+
+    #[test]
+    fn passed_output() {
+        let mut cpu = rom_printing("01-special Passed");
+        let (result, output) = run(&mut cpu, crate::CYCLES_PER_SECOND);
+        assert_eq!(result, TestResult::Passed);
+        assert!(output.ends_with("Passed"));
+    }
+
+    #[test]
+    fn failed_output() {
+        let mut cpu = rom_printing("Failed #3");
+        let (result, output) = run(&mut cpu, crate::CYCLES_PER_SECOND);
+        assert_eq!(result, TestResult::Failed);
+        assert_eq!(output, "Failed #3");
+    }
+
+    #[test]
+    fn silent_rom_times_out() {
+        let mut cpu = rom_printing("");
+        let (result, output) = run(&mut cpu, crate::CYCLES_PER_FRAME * 10);
+        assert_eq!(result, TestResult::TimedOut);
+        assert_eq!(output, "");
+    }
+}
@@ -0,0 +1,253 @@
+//! Runner for the mooneye test suite. Mooneye ROMs signal the end of a test
+//! by executing `LD B,B` with the Fibonacci numbers 3, 5, 8, 13, 21, 34 in
+//! B, C, D, E, H, L when they pass, or 0x42 in every register when they fail.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{load_rom, TestResult};
+use crate::cpu::CPU;
+use crate::model::Model;
+
+pub const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+pub const FAIL_REGISTERS: [u8; 6] = [0x42; 6];
+
+/// Groups of the suite that are run by `run_directory`
+pub const GROUPS: [&str; 2] = ["acceptance", "emulator-only"];
+
+/// Runs a mooneye ROM until it reaches the `LD B,B` breakpoint or
+/// `timeout_cycles` T-cycles have been emulated.
+pub fn run(cpu: &mut CPU, timeout_cycles: u64) -> TestResult {
+    cpu.test_mode = true;
+    cpu.breakpoint_hit = false;
+    let start = cpu.cycles;
+
+    while !cpu.breakpoint_hit {
+        if cpu.cycles - start >= timeout_cycles {
+            return TestResult::TimedOut;
+        }
+        if let Err(error) = cpu.step() {
+            return TestResult::Crashed(error);
+        }
+    }
+
+    let registers = &cpu.registers;
+    let values = [
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ];
+    if values == PASS_REGISTERS {
+        TestResult::Passed
+    } else {
+        // 0x42 everywhere is the documented failure, anything else means the
+        // test went off the rails
+        TestResult::Failed
+    }
+}
+
+/// Model a ROM is meant for, from the suffix of its name: `boot_regs-dmg0`
+/// targets the DMG0, `boot_regs-dmgABC` the DMG and so on. ROMs without a
+/// suffix run on the DMG.
+pub fn model_for(path: &Path) -> Model {
+    let name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    let suffix = match name.rsplit_once('-') {
+        Some((_, suffix)) => suffix,
+        None => return Model::DMG,
+    };
+    if suffix.starts_with("dmg0") {
+        Model::DMG0
+    } else if suffix.starts_with("dmg") || suffix.starts_with('G') {
+        Model::DMG
+    } else if suffix.starts_with("mgb") {
+        Model::MGB
+    } else if suffix.starts_with("sgb2") {
+        Model::SGB2
+    } else if suffix.starts_with("sgb") || suffix.starts_with('S') {
+        Model::SGB
+    } else if suffix.starts_with("cgb") || suffix.starts_with('C') {
+        Model::CGB
+    } else if suffix.starts_with('A') {
+        Model::AGB
+    } else {
+        Model::DMG
+    }
+}
+
+/// Loads and runs the mooneye ROM at `path` on the model it targets
+pub fn run_rom(path: &Path, timeout_cycles: u64) -> io::Result<TestResult> {
+    let mut cpu = load_rom(path, model_for(path))?;
+    Ok(run(&mut cpu, timeout_cycles))
+}
+
+/// Results of the ROMs of one group, with paths relative to the group
+pub struct GroupReport {
+    pub group: String,
+    pub results: Vec<(PathBuf, io::Result<TestResult>)>,
+}
+
+impl GroupReport {
+    pub fn passed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, result)| matches!(result, Ok(TestResult::Passed)))
+            .count()
+    }
+}
+
+/// Runs every ROM of the acceptance and emulator-only groups found in the
+/// mooneye build directory `dir`. Missing groups are left out.
+pub fn run_directory(dir: &Path, timeout_cycles: u64) -> io::Result<Vec<GroupReport>> {
+    let mut reports = Vec::new();
+    for group in GROUPS {
+        let group_dir = dir.join(group);
+        if !group_dir.is_dir() {
+            continue;
+        }
+        let mut roms = Vec::new();
+        find_roms(&group_dir, &mut roms)?;
+        roms.sort();
+
+        let results = roms
+            .into_iter()
+            .map(|rom| {
+                let result = run_rom(&rom, timeout_cycles);
+                let name = rom.strip_prefix(&group_dir).unwrap_or(&rom).to_path_buf();
+                (name, result)
+            })
+            .collect();
+        reports.push(GroupReport {
+            group: group.to_string(),
+            results,
+        });
+    }
+    Ok(reports)
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Prints one line per ROM and a summary per group
+pub fn print_matrix(reports: &[GroupReport], output: &mut impl Write) -> io::Result<()> {
+    for report in reports {
+        writeln!(output, "{}", report.group)?;
+        let width = report
+            .results
+            .iter()
+            .map(|(name, _)| name.display().to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (name, result) in &report.results {
+            let status = match result {
+                Ok(TestResult::Passed) => "PASS".to_string(),
+                Ok(TestResult::Failed) => "FAIL".to_string(),
+                Ok(TestResult::TimedOut) => "TIMEOUT".to_string(),
+                Ok(TestResult::Crashed(error)) => format!("CRASH ({})", error),
+                Err(error) => format!("ERROR ({})", error),
+            };
+            let name = name.display().to_string();
+            writeln!(output, "  {:<width$}  {}", name, status, width = width)?;
+        }
+        writeln!(
+            output,
+            "  {}/{} passed",
+            report.passed(),
+            report.results.len()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_at;
+
+    fn rom_with_registers(values: [u8; 6]) -> CPU {
+        let program = assemble_at(
+            &format!(
+                "
+                    ld b, {}
+                    ld c, {}
+                    ld d, {}
+                    ld e, {}
+                    ld h, {}
+                    ld l, {}
+                    ld b, b
+                loop:
+                    jr loop
+                ",
+                values[0], values[1], values[2], values[3], values[4], values[5]
+            ),
+            0x100,
+        )
+        .unwrap();
+        let mut cpu = CPU::with_model(Model::DMG);
+        cpu.bus.cartridge.rom_mut()[0x100..0x100 + program.len()].copy_from_slice(&program);
+        cpu
+    }
+
+    #[test]
+    fn fibonacci_registers_pass() {
+        let mut cpu = rom_with_registers(PASS_REGISTERS);
+        assert_eq!(run(&mut cpu, crate::CYCLES_PER_FRAME), TestResult::Passed);
+    }
+
+    #[test]
+    fn failure_registers_fail() {
+        let mut cpu = rom_with_registers(FAIL_REGISTERS);
+        assert_eq!(run(&mut cpu, crate::CYCLES_PER_FRAME), TestResult::Failed);
+    }
+
+    #[test]
+    fn missing_breakpoint_times_out() {
+        let mut cpu = CPU::with_model(Model::DMG);
+        cpu.bus.cartridge.rom_mut()[0x100] = 0x18; // JR -2
+        cpu.bus.cartridge.rom_mut()[0x101] = 0xFE;
+        assert_eq!(run(&mut cpu, crate::CYCLES_PER_FRAME), TestResult::TimedOut);
+    }
+
+    #[test]
+    fn model_from_rom_name() {
+        assert_eq!(model_for(Path::new("add_sp_e_timing.gb")), Model::DMG);
+        assert_eq!(model_for(Path::new("boot_regs-dmg0.gb")), Model::DMG0);
+        assert_eq!(model_for(Path::new("boot_regs-dmgABC.gb")), Model::DMG);
+        assert_eq!(model_for(Path::new("boot_regs-mgb.gb")), Model::MGB);
+        assert_eq!(model_for(Path::new("boot_regs-sgb2.gb")), Model::SGB2);
+        assert_eq!(model_for(Path::new("boot_div-S.gb")), Model::SGB);
+        assert_eq!(model_for(Path::new("boot_hwio-C.gb")), Model::CGB);
+    }
+
+    #[test]
+    fn matrix_lists_every_rom() {
+        let reports = vec![GroupReport {
+            group: "acceptance".to_string(),
+            results: vec![
+                (PathBuf::from("bits/reg_f.gb"), Ok(TestResult::Passed)),
+                (PathBuf::from("ei_sequence.gb"), Ok(TestResult::Failed)),
+            ],
+        }];
+        let mut output = Vec::new();
+        print_matrix(&reports, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "acceptance\n  bits/reg_f.gb   PASS\n  ei_sequence.gb  FAIL\n  1/2 passed\n"
+        );
+    }
+}
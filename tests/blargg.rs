@@ -0,0 +1,63 @@
+//! Runs Blargg's cpu_instrs and instr_timing ROMs.
+//!
+//! The ROMs aren't part of the repository: point `BLARGG_ROMS_DIR` at a
+//! directory containing them (subdirectories are searched too), or copy them
+//! to `tests/roms/blargg`. The test is skipped when the directory is missing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dmg_01::test_roms::{blargg, TestResult};
+
+const TIMEOUT_SECONDS: u64 = 120;
+
+fn roms_dir() -> PathBuf {
+    match std::env::var_os("BLARGG_ROMS_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/blargg"),
+    }
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+fn blargg_test_roms() {
+    let dir = roms_dir();
+    if !dir.is_dir() {
+        eprintln!("skipping: no Blargg test ROMs in {}", dir.display());
+        return;
+    }
+    let mut roms = Vec::new();
+    find_roms(&dir, &mut roms);
+    roms.sort();
+
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let timeout = TIMEOUT_SECONDS * dmg_01::CYCLES_PER_SECOND;
+        match blargg::run_rom(rom, timeout) {
+            Ok((TestResult::Passed, _)) => {}
+            Ok((result, output)) => {
+                failures.push(format!("{}: {}\n{}", rom.display(), result, output))
+            }
+            Err(error) => failures.push(format!("{}: {}", rom.display(), error)),
+        }
+    }
+    for failure in &failures {
+        eprintln!("{}", failure);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} ROMs failed",
+        failures.len(),
+        roms.len()
+    );
+}
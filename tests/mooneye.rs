@@ -0,0 +1,36 @@
+//! Runs the acceptance and emulator-only groups of the mooneye test suite.
+//!
+//! The ROMs aren't part of the repository: point `MOONEYE_ROMS_DIR` at the
+//! mooneye build directory, or copy it to `tests/roms/mooneye`. The test is
+//! skipped when the directory is missing.
+
+use std::path::{Path, PathBuf};
+
+use dmg_01::test_roms::mooneye;
+
+const TIMEOUT_SECONDS: u64 = 30;
+
+fn roms_dir() -> PathBuf {
+    match std::env::var_os("MOONEYE_ROMS_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/mooneye"),
+    }
+}
+
+#[test]
+fn mooneye_test_roms() {
+    let dir = roms_dir();
+    if !dir.is_dir() {
+        eprintln!("skipping: no mooneye test ROMs in {}", dir.display());
+        return;
+    }
+    let timeout = TIMEOUT_SECONDS * dmg_01::CYCLES_PER_SECOND;
+    let reports = mooneye::run_directory(&dir, timeout).unwrap();
+    mooneye::print_matrix(&reports, &mut std::io::stderr()).unwrap();
+
+    let failed: usize = reports
+        .iter()
+        .map(|report| report.results.len() - report.passed())
+        .sum();
+    assert_eq!(failed, 0, "{} mooneye ROMs failed", failed);
+}
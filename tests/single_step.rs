@@ -0,0 +1,255 @@
+//! Conformance harness for the SingleStepTests sm83 JSON test vectors
+//! (https://github.com/SingleStepTests/sm83).
+//!
+//! The vectors aren't part of the repository: point `SM83_TESTS_DIR` at the
+//! directory holding the `*.json` files, or copy them to `tests/data/sm83`.
+//! `tests/data/sm83_sample.json` holds a few hand-written vectors in the same
+//! format so the harness itself is always exercised.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use dmg_01::bus::{Bus, BusAccess, FlatBus, RecordingBus};
+use dmg_01::cpu::flags_register::FlagsRegister;
+use dmg_01::cpu::CPU;
+
+// STOP and HALT depend on the rest of the hardware and aren't covered by a
+// single instruction step.
+const SKIPPED_OPCODES: [&str; 2] = ["10", "76"];
+
+const MAX_REPORTED_FAILURES: usize = 20;
+
+fn tests_dir() -> PathBuf {
+    match std::env::var_os("SM83_TESTS_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sm83"),
+    }
+}
+
+fn number(state: &Value, key: &str) -> u16 {
+    state[key]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing `{}`", key)) as u16
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("missing `ram`")
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}
+
+// memory accesses of the `cycles` log, internal cycles are `null` or have
+// neither `r` nor `w` in their pin string
+fn expected_accesses(cycles: &[Value]) -> Vec<BusAccess> {
+    cycles
+        .iter()
+        .filter_map(|cycle| {
+            let address = cycle[0].as_u64()? as u16;
+            let value = cycle[1].as_u64()? as u8;
+            let pins = cycle[2].as_str()?;
+            if pins.contains('r') {
+                Some(BusAccess::Read { address, value })
+            } else if pins.contains('w') {
+                Some(BusAccess::Write { address, value })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// opcode bytes encoded in the vector name, e.g. "cb 7c 0000"
+fn opcode_bytes(name: &str) -> Vec<u8> {
+    let mut parts = name.split_whitespace();
+    let opcode = u8::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    if opcode == 0xCB {
+        vec![0xCB, u8::from_str_radix(parts.next().unwrap(), 16).unwrap()]
+    } else {
+        vec![opcode]
+    }
+}
+
+fn run_vector(vector: &Value) -> Result<(), String> {
+    let name = vector["name"].as_str().unwrap_or("?");
+    let initial = &vector["initial"];
+    let expected = &vector["final"];
+    let cycles = vector["cycles"].as_array().expect("missing `cycles`");
+
+    // the vectors treat the whole address space as RAM
+    let mut cpu = CPU::with_bus(RecordingBus::new(FlatBus::new()));
+    cpu.registers.a = number(initial, "a") as u8;
+    cpu.registers.b = number(initial, "b") as u8;
+    cpu.registers.c = number(initial, "c") as u8;
+    cpu.registers.d = number(initial, "d") as u8;
+    cpu.registers.e = number(initial, "e") as u8;
+    cpu.registers.f = FlagsRegister::from(number(initial, "f") as u8);
+    cpu.registers.h = number(initial, "h") as u8;
+    cpu.registers.l = number(initial, "l") as u8;
+    cpu.sp = number(initial, "sp");
+    cpu.pc = number(initial, "pc");
+    cpu.interrupts_enabled = number(initial, "ime") != 0;
+    cpu.bus.inner.memory[0xFFFF] = initial["ie"].as_u64().unwrap_or(0) as u8;
+    for (address, value) in ram(initial) {
+        cpu.bus.inner.memory[address as usize] = value;
+    }
+
+    // The vectors model the SM83 fetch overlap: the opcode at PC - 1 was
+    // fetched by the previous instruction and the step ends by fetching the
+    // next opcode. Vectors with the opcode at PC are run as a plain step.
+    let opcode = opcode_bytes(name);
+    let prefetched = opcode.iter().enumerate().all(|(offset, byte)| {
+        cpu.bus
+            .peek(cpu.pc.wrapping_sub(1).wrapping_add(offset as u16))
+            == *byte
+    });
+    if prefetched {
+        cpu.pc = cpu.pc.wrapping_sub(1);
+    }
+
+    let m_cycles = cpu.step().map_err(|error| error.to_string())? as usize / 4;
+    if prefetched {
+        cpu.read_byte(cpu.pc);
+        cpu.pc = cpu.pc.wrapping_add(1);
+    }
+    let mut accesses: Vec<BusAccess> = cpu
+        .bus
+        .accesses
+        .iter()
+        .copied()
+        .filter(|access| *access != BusAccess::Idle)
+        .collect();
+    if prefetched {
+        // the first fetch belongs to the previous instruction
+        accesses.remove(0);
+    }
+
+    let mut errors = Vec::new();
+    let mut check = |what: &str, actual: u16, expected: u16| {
+        if actual != expected {
+            errors.push(format!(
+                "{}: expected {:#06X}, got {:#06X}",
+                what, expected, actual
+            ));
+        }
+    };
+    check("A", cpu.registers.a as u16, number(expected, "a"));
+    check("B", cpu.registers.b as u16, number(expected, "b"));
+    check("C", cpu.registers.c as u16, number(expected, "c"));
+    check("D", cpu.registers.d as u16, number(expected, "d"));
+    check("E", cpu.registers.e as u16, number(expected, "e"));
+    check("F", u8::from(cpu.registers.f) as u16, number(expected, "f"));
+    check("H", cpu.registers.h as u16, number(expected, "h"));
+    check("L", cpu.registers.l as u16, number(expected, "l"));
+    check("SP", cpu.sp, number(expected, "sp"));
+    check("PC", cpu.pc, number(expected, "pc"));
+    // the EI delay can't be observed within a single instruction
+    let ime = cpu.interrupts_enabled || cpu.ime_pending;
+    check("IME", ime as u16, number(expected, "ime"));
+    for (address, value) in ram(expected) {
+        let what = format!("[{:#06X}]", address);
+        check(&what, cpu.bus.peek(address) as u16, value as u16);
+    }
+
+    // with the fetch overlap the fetch of the next opcode takes the place of
+    // the fetch of this one, so the number of M-cycles is the same
+    if m_cycles != cycles.len() {
+        errors.push(format!(
+            "expected {} M-cycles, got {}",
+            cycles.len(),
+            m_cycles
+        ));
+    }
+    let expected_accesses = expected_accesses(cycles);
+    if accesses != expected_accesses {
+        errors.push(format!(
+            "bus accesses: expected {:?}, got {:?}",
+            expected_accesses, accesses
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+fn is_supported(vector: &Value) -> bool {
+    let name = vector["name"].as_str().unwrap_or("");
+    !SKIPPED_OPCODES
+        .iter()
+        .any(|opcode| name.starts_with(opcode))
+}
+
+// returns the number of vectors run and the failure messages
+fn run_file(path: &Path) -> (usize, Vec<String>) {
+    let text = fs::read_to_string(path).unwrap();
+    let vectors: Vec<Value> =
+        serde_json::from_str(&text).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+
+    let mut run = 0;
+    let mut failures = Vec::new();
+    for vector in vectors.iter().filter(|vector| is_supported(vector)) {
+        run += 1;
+        if let Err(error) = run_vector(vector) {
+            let name = vector["name"].as_str().unwrap_or("?");
+            failures.push(format!("{} `{}`: {}", path.display(), name, error));
+        }
+    }
+    (run, failures)
+}
+
+fn assert_no_failures(run: usize, failures: &[String]) {
+    if !failures.is_empty() {
+        for failure in failures.iter().take(MAX_REPORTED_FAILURES) {
+            eprintln!("{}", failure);
+        }
+        panic!("{} of {} vectors failed", failures.len(), run);
+    }
+}
+
+#[test]
+fn sample_vectors() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/sm83_sample.json");
+    let (run, failures) = run_file(&path);
+    assert_eq!(run, 6);
+    assert_no_failures(run, &failures);
+}
+
+#[test]
+fn sm83_test_vectors() {
+    let dir = tests_dir();
+    let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect(),
+        Err(_) => {
+            eprintln!("skipping: no sm83 test vectors in {}", dir.display());
+            return;
+        }
+    };
+    paths.sort();
+
+    let mut run = 0;
+    let mut failures = Vec::new();
+    for path in paths {
+        let (file_run, file_failures) = run_file(&path);
+        run += file_run;
+        failures.extend(file_failures);
+    }
+    assert_no_failures(run, &failures);
+}